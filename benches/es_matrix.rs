@@ -0,0 +1,57 @@
+//! Times the matrix paths a frame goes through, the scalar multiply next to whatever
+//! `multiply` compiles to. Compare the two builds with
+//!
+//! ```sh
+//! cargo bench --bench es_matrix
+//! cargo bench --bench es_matrix --features simd
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use kmscube::es_matrix::ESMatrix;
+use kmscube::vector::Vec4;
+
+const ITERATIONS: u32 = 2_000_000;
+
+/// Runs `f` `iterations` times after a short warm up and prints the time per call.
+fn bench<F: FnMut() -> T, T>(name: &str, iterations: u32, mut f: F) -> f64 {
+    for _ in 0..iterations / 10 {
+        black_box(f());
+    }
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    let nanos = start.elapsed().as_secs_f64() * 1e9 / iterations as f64;
+
+    println!("{:<24} {:>10.2} ns", name, nanos);
+    nanos
+}
+
+fn main() {
+    // `cargo test` runs benches without `--bench`, a single pass is enough to check they work
+    let iterations = if std::env::args().any(|arg| arg == "--bench") { ITERATIONS } else { 1 };
+    println!("simd feature {}", if cfg!(feature = "simd") { "on" } else { "off" });
+
+    let a = ESMatrix::identity().translate(1.0, -2.0, 3.0).rotate(40.0, 0.0, 1.0, 1.0);
+    let b = ESMatrix::identity().perspective(45.0, 1.5, 1.0, 100.0);
+    let v = Vec4::new(0.3, -0.7, 1.1, 1.0);
+
+    let scalar = bench("multiply_scalar", iterations, || ESMatrix::multiply_scalar(black_box(a), black_box(b)));
+    let multiply = bench("multiply", iterations, || ESMatrix::multiply(black_box(a), black_box(b)));
+    bench("rotate", iterations, || black_box(a).rotate(black_box(30.0), 1.0, 1.0, 0.0));
+    bench("frustum", iterations, || black_box(a).frustum(-1.0, 1.0, -1.0, 1.0, 1.0, black_box(10.0)));
+    bench("matrix * vector", iterations, || black_box(a) * black_box(v));
+
+    // a frame's worth of objects: model, view and projection for each
+    bench("1000 model-view-proj", (iterations / 1000).max(1), || {
+        for i in 0..1000 {
+            let model = ESMatrix::identity().translate(i as f32, 0.0, -8.0).rotate(i as f32, 0.0, 1.0, 0.0);
+            black_box(ESMatrix::multiply(ESMatrix::multiply(model, black_box(a)), black_box(b)));
+        }
+    });
+
+    println!("multiply speedup {:.2}x", scalar / multiply);
+}
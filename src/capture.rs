@@ -0,0 +1,113 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+use gbm::Format;
+use sys::gles2 as gl;
+
+/// Writes rendered frames to PNG files.
+#[derive(Debug, Clone)]
+pub struct Capture {
+    dir: PathBuf,
+    frames: Vec<u32>,
+    every: Option<u32>,
+}
+
+impl Capture {
+    /// Captures the listed `frames` and, unless `None`, every `every`th one into `dir`.
+    pub fn new<P: Into<PathBuf>>(dir: P, frames: Vec<u32>, every: Option<u32>) -> Capture {
+        Capture { dir: dir.into(), frames, every }
+    }
+
+    pub fn wants(&self, frame: u32) -> bool {
+        self.frames.contains(&frame) || self.every.map_or(false, |every| every > 0 && frame % every == 0)
+    }
+
+    /// Reads back the frame currently in the bound framebuffer and writes it as
+    /// `frame-NNNNN.png`. Has to run after drawing and before swapping buffers.
+    pub fn save(&self, frame: u32, width: u32, height: u32, format: Format) -> io::Result<PathBuf> {
+        let pixels = read_pixels(width, height, format)?;
+
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("frame-{:05}.png", frame));
+        write_png(&path, width, height, &pixels)?;
+
+        Ok(path)
+    }
+}
+
+/// Saves the frame and reports where it went, a failed capture doesn't stop the demo.
+pub fn save_frame(capture: &Capture, frame: u32, width: u32, height: u32, format: Format) {
+    match capture.save(frame, width, height, format) {
+        Ok(path) => println!("captured frame {} to {}", frame, path.display()),
+        Err(e) => eprintln!("failed to capture frame {}: {}", frame, e),
+    }
+}
+
+/// Whether the scanout format carries alpha, for the X formats the padding bits are undefined.
+fn has_alpha(format: Format) -> bool {
+    matches!(format, Format::Argb8888 | Format::Abgr8888)
+}
+
+/// Whether the color buffer holds floating point values rather than normalized ones.
+fn is_float(format: Format) -> bool {
+    matches!(format, Format::Xbgr16161616f | Format::Abgr16161616f)
+}
+
+/// Reads the framebuffer as top-down RGBA8.
+///
+/// Normalized color buffers (565, 8888, 2101010, ...) can always be read as
+/// RGBA/UNSIGNED_BYTE, GL converts from the surface format itself. For floating point ones
+/// only RGBA/FLOAT is guaranteed, those are read that way and clamped to 8 bits here.
+pub fn read_pixels(width: u32, height: u32, format: Format) -> io::Result<Vec<u8>> {
+    let stride = width as usize * 4;
+    let len = stride * height as usize;
+
+    // errors left over from rendering would be blamed on the read otherwise
+    while unsafe { gl::GetError() } != gl::NO_ERROR {}
+
+    let mut pixels = if is_float(format) {
+        let mut floats = vec![0_f32; len];
+        read(width, height, gl::FLOAT, floats.as_mut_ptr() as *mut _)?;
+        floats.iter().map(|&c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect()
+    } else {
+        let mut bytes = vec![0_u8; len];
+        read(width, height, gl::UNSIGNED_BYTE, bytes.as_mut_ptr() as *mut _)?;
+        bytes
+    };
+
+    if !has_alpha(format) {
+        pixels.chunks_mut(4).for_each(|pixel| pixel[3] = 0xff);
+    }
+
+    // GL rows start at the bottom
+    let mut flipped = Vec::with_capacity(pixels.len());
+    pixels.chunks(stride).rev().for_each(|row| flipped.extend_from_slice(row));
+
+    Ok(flipped)
+}
+
+/// glReadPixels of the whole frame as RGBA components of type `kind` into `data`, which has
+/// to have room for them.
+fn read(width: u32, height: u32, kind: gl::types::GLenum, data: *mut std::ffi::c_void) -> io::Result<()> {
+    unsafe {
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+        gl::ReadPixels(0, 0, width as i32, height as i32, gl::RGBA, kind, data as *mut _);
+    }
+
+    match unsafe { gl::GetError() } {
+        gl::NO_ERROR => Ok(()),
+        error => Err(io::Error::new(io::ErrorKind::Other, format!("glReadPixels failed: 0x{:x}", error))),
+    }
+}
+
+pub fn write_png(path: &Path, width: u32, height: u32, pixels: &[u8]) -> io::Result<()> {
+    let file = File::create(path)?;
+
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let mut writer = encoder.write_header().map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    writer.write_image_data(pixels).map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+}
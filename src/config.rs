@@ -0,0 +1,104 @@
+//! What to display on and how, filled in by whoever embeds the library. The binary builds
+//! it from the command line.
+
+use gbm::Format;
+
+use crate::capture::Capture;
+
+/// Connector to drive, either its position in the resource list or its name as the kernel
+/// reports it, e.g. `HDMI-A-1`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConnectorSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl std::fmt::Display for ConnectorSelector {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ConnectorSelector::Index(index) => write!(f, "#{}", index),
+            ConnectorSelector::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// Video mode by name, e.g. `1920x1080`, optionally with a refresh rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoMode {
+    pub name: String,
+    pub vrefresh: Option<u32>,
+}
+
+impl std::fmt::Display for VideoMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.vrefresh {
+            Some(vrefresh) => write!(f, "{}@{}", self.name, vrefresh),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Framebuffer formats that can be requested, by their fourcc.
+pub const FORMATS: &[(&str, Format)] = &[
+    ("XR24", Format::Xrgb8888),
+    ("XB24", Format::Xbgr8888),
+    ("AR24", Format::Argb8888),
+    ("AB24", Format::Abgr8888),
+    ("RG16", Format::Rgb565),
+    ("XR30", Format::Xrgb2101010),
+    ("XB30", Format::Xbgr2101010),
+    ("XB4H", Format::Xbgr16161616f),
+];
+
+/// The fourcc of `format`.
+pub fn format_name(format: Format) -> &'static str {
+    FORMATS.iter()
+        .find(|&&(_, f)| f == format)
+        .map_or("unknown", |&(fourcc, _)| fourcc)
+}
+
+#[derive(Debug, Clone)]
+pub struct DisplayConfig {
+    /// DRM device node.
+    pub device: String,
+    /// The first connected connector if `None`.
+    pub connector: Option<ConnectorSelector>,
+    /// The preferred mode if `None`, else the largest one. Headless runs take their size
+    /// from a `<width>x<height>` name.
+    pub video_mode: Option<VideoMode>,
+    /// Falls back to XRGB8888 if the plane or EGL can't use it.
+    pub format: Format,
+    pub modifier: Option<u64>,
+    /// Force `DRM_FORMAT_MOD_LINEAR`, overriding `modifier`.
+    pub linear: bool,
+    /// MSAA samples, none if 0.
+    pub samples: u32,
+    /// Use atomic modesetting, falling back to legacy.
+    pub atomic: bool,
+    /// Print frame rate and timings every 5 seconds.
+    pub perf: bool,
+    /// Write per frame timings here at exit, implies `perf`.
+    pub perf_report: Option<String>,
+    pub capture: Option<Capture>,
+    /// Report framebuffer and buffer object counts every 1000 frames and at exit.
+    pub soak: bool,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        DisplayConfig {
+            device: "/dev/dri/card0".to_owned(),
+            connector: None,
+            video_mode: None,
+            format: Format::Xrgb8888,
+            modifier: None,
+            linear: false,
+            samples: 0,
+            atomic: false,
+            perf: false,
+            perf_report: None,
+            capture: None,
+            soak: false,
+        }
+    }
+}
@@ -1,89 +1,231 @@
-use gbm::{Device, Format};
+use std::time::Duration;
 
-use std::ffi::CString;
+use gbm::Format;
+use sys::gles2 as gl;
+use sys::gles2::types::{GLint, GLuint};
 
-use sys::{gles2 as gl, Card};
-
-use crate::egl;
+use crate::error::Result;
 use crate::es_matrix::ESMatrix;
+use crate::hot_reload::ShaderFiles;
+use crate::renderer::Renderer;
+use crate::shader::ShaderProgram;
+
+/// Positions of the cube faces, each a triangle strip of four vertices.
+pub const VERTICES: [f32; 12 * 6] = [
+    -1.0, -1.0, 1.0,
+    1.0, -1.0, 1.0,
+    -1.0, 1.0, 1.0,
+    1.0, 1.0, 1.0, // front
+
+    1.0, -1.0, -1.0,
+    -1.0, -1.0, -1.0,
+    1.0, 1.0, -1.0,
+    -1.0, 1.0, -1.0, // back
+
+    1.0, -1.0, 1.0,
+    1.0, -1.0, -1.0,
+    1.0, 1.0, 1.0,
+    1.0, 1.0, -1.0, // right
+
+    -1.0, -1.0, -1.0,
+    -1.0, -1.0, 1.0,
+    -1.0, 1.0, -1.0,
+    -1.0, 1.0, 1.0, // left
+
+    -1.0, 1.0, 1.0,
+    1.0, 1.0, 1.0,
+    -1.0, 1.0, -1.0,
+    1.0, 1.0, -1.0, // top
+
+    -1.0, -1.0, -1.0,
+    1.0, -1.0, -1.0,
+    -1.0, -1.0, 1.0,
+    1.0, -1.0, 1.0, // bottom
+];
+
+/// Per vertex normals matching `VERTICES`.
+pub const NORMALS: [f32; 12 * 6] = [
+    // front
+    0.0, 0.0, 1.0, // forward
+    0.0, 0.0, 1.0, // forward
+    0.0, 0.0, 1.0, // forward
+    0.0, 0.0, 1.0, // forward
+    // back
+    0.0, 0.0, -1.0, // backward
+    0.0, 0.0, -1.0, // backward
+    0.0, 0.0, -1.0, // backward
+    0.0, 0.0, -1.0, // backward
+    // right
+    1.0, 0.0, 0.0, // right
+    1.0, 0.0, 0.0, // right
+    1.0, 0.0, 0.0, // right
+    1.0, 0.0, 0.0, // right
+    // left
+    -1.0, 0.0, 0.0, // left
+    -1.0, 0.0, 0.0, // left
+    -1.0, 0.0, 0.0, // left
+    -1.0, 0.0, 0.0, // left
+    // top
+    0.0, 1.0, 0.0, // up
+    0.0, 1.0, 0.0, // up
+    0.0, 1.0, 0.0, // up
+    0.0, 1.0, 0.0, // up
+    // bottom
+    0.0, -1.0, 0.0, // down
+    0.0, -1.0, 0.0, // down
+    0.0, -1.0, 0.0, // down
+    0.0, -1.0, 0.0  // down
+];
+
+/// Vertical field of view in degrees for `aspect`, keeping the original frustum's 5.6 units of
+/// width at the near plane 6 units away whatever the output's shape. That's 38.58 on 4:3.
+fn fovy(aspect: f32) -> f32 {
+    2.0 * (2.8 / aspect / 6.0).atan().to_degrees()
+}
+
+/// Built in shaders, `--vertex-shader` and `--fragment-shader` replace them.
+const VERTEX_SHADER: &str = r#"
+    uniform mat4 modelviewMatrix;
+    uniform mat4 modelviewprojectionMatrix;
+    uniform mat3 normalMatrix;
+
+    attribute vec4 in_position;
+    attribute vec3 in_normal;
+    attribute vec4 in_color;
+
+    vec4 lightSource = vec4(2.0, 2.0, 20.0, 0.0);
+
+    varying vec4 vVaryingColor;
+
+    void main()
+    {
+        gl_Position = modelviewprojectionMatrix * in_position;
+        vec3 vEyeNormal = normalMatrix * in_normal;
+        vec4 vPosition4 = modelviewMatrix * in_position;
+        vec3 vPosition3 = vPosition4.xyz / vPosition4.w;
+        vec3 vLightDir = normalize(lightSource.xyz - vPosition3);
+        float diff = max(0.0, dot(vEyeNormal, vLightDir));
+        vVaryingColor = vec4(diff * in_color.rgb, 1.0);
+    }
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+    precision mediump float;
+
+    varying vec4 vVaryingColor;
+
+    void main()
+    {
+        gl_FragColor = vVaryingColor;
+    }
+"#;
+
+/// Locations of the matrix uniforms the cube demos share.
+#[derive(Default)]
+pub struct MatrixUniforms {
+    pub modelview: GLint,
+    pub modelviewprojection: GLint,
+    pub normal: GLint,
+}
 
-pub fn init(
-    gbm: &Device<Card>,
-    mode: &drm::control::Mode,
-    samples: u32,
-    gbm_surface: &gbm::Surface<Card>,
-    pixel_format: Format
-) -> (sys::egl::types::EGLDisplay, sys::egl::types::EGLSurface, i32, i32, i32) {
-    let (result_d, result_s) = egl::init(gbm, samples, gbm_surface, pixel_format);
-
-    let vertex_shader_source = r#"
-        uniform mat4 modelviewMatrix;
-        uniform mat4 modelviewprojectionMatrix;
-        uniform mat3 normalMatrix;
-
-        attribute vec4 in_position;
-        attribute vec3 in_normal;
-        attribute vec4 in_color;
-
-        vec4 lightSource = vec4(2.0, 2.0, 20.0, 0.0);
-
-        varying vec4 vVaryingColor;
-
-        void main()
-        {
-            gl_Position = modelviewprojectionMatrix * in_position;
-            vec3 vEyeNormal = normalMatrix * in_normal;
-            vec4 vPosition4 = modelviewMatrix * in_position;
-            vec3 vPosition3 = vPosition4.xyz / vPosition4.w;
-            vec3 vLightDir = normalize(lightSource.xyz - vPosition3);
-            float diff = max(0.0, dot(vEyeNormal, vLightDir));
-            vVaryingColor = vec4(diff * in_color.rgb, 1.0);
+impl MatrixUniforms {
+    pub fn locate(program: &ShaderProgram) -> Self {
+        MatrixUniforms {
+            modelview: program.uniform_location("modelviewMatrix"),
+            modelviewprojection: program.uniform_location("modelviewprojectionMatrix"),
+            normal: program.uniform_location("normalMatrix"),
         }
-    "#;
+    }
+}
 
-    let fragment_shader_source = r#"
-        precision mediump float;
+/// Smooth shaded cube with per vertex colors.
+#[derive(Default)]
+pub struct CubeSmooth {
+    program: Option<ShaderProgram>,
+    vbo: GLuint,
+    uniforms: MatrixUniforms,
+    aspect: f32,
+    shader_files: Option<ShaderFiles>,
+}
 
-        varying vec4 vVaryingColor;
+impl CubeSmooth {
+    /// Uses the built in shaders where `shader_files` doesn't give any.
+    pub fn new(shader_files: Option<ShaderFiles>) -> Self {
+        CubeSmooth { shader_files, ..CubeSmooth::default() }
+    }
 
-        void main()
-        {
-            gl_FragColor = vVaryingColor;
+    fn shader_sources(&self) -> Result<(String, String)> {
+        match self.shader_files {
+            Some(ref files) => files.load(VERTEX_SHADER, FRAGMENT_SHADER),
+            None => Ok((VERTEX_SHADER.to_owned(), FRAGMENT_SHADER.to_owned())),
         }
-    "#;
-
-    let vertices: [f32; 12 * 6] = [
-        -1.0, -1.0, 1.0,
-        1.0, -1.0, 1.0,
-        -1.0, 1.0, 1.0,
-        1.0, 1.0, 1.0, // front
-
-        1.0, -1.0, -1.0,
-        -1.0, -1.0, -1.0,
-        1.0, 1.0, -1.0,
-        -1.0, 1.0, -1.0, // back
-
-        1.0, -1.0, 1.0,
-        1.0, -1.0, -1.0,
-        1.0, 1.0, 1.0,
-        1.0, 1.0, -1.0, // right
-
-        -1.0, -1.0, -1.0,
-        -1.0, -1.0, 1.0,
-        -1.0, 1.0, -1.0,
-        -1.0, 1.0, 1.0, // left
-
-        -1.0, 1.0, 1.0,
-        1.0, 1.0, 1.0,
-        -1.0, 1.0, -1.0,
-        1.0, 1.0, -1.0, // top
-
-        -1.0, -1.0, -1.0,
-        1.0, -1.0, -1.0,
-        -1.0, -1.0, 1.0,
-        1.0, -1.0, 1.0, // bottom
-    ];
+    }
+
+    /// Rebuilds the program once the shader files changed, the previous one stays in use if
+    /// the new sources don't compile or link.
+    fn reload_shaders(&mut self) {
+        if !self.shader_files.as_mut().map_or(false, ShaderFiles::changed) {
+            return;
+        }
+
+        match self.shader_sources().and_then(|(vertex, fragment)| build_program(&vertex, &fragment)) {
+            Ok(program) => {
+                program.bind();
+                self.uniforms = MatrixUniforms::locate(&program);
+                self.program = Some(program);
+                println!("shaders reloaded");
+            }
+            Err(e) => eprintln!("shader reload failed, keeping the previous program: {}", e),
+        }
+    }
+}
+
+impl Renderer for CubeSmooth {
+    fn init(&mut self, width: u32, height: u32, _format: Format) -> Result<()> {
+        let (vertex, fragment) = self.shader_sources()?;
+        let (program, vbo) = setup(&vertex, &fragment)?;
+
+        self.uniforms = MatrixUniforms::locate(&program);
+        self.program = Some(program);
+        self.vbo = vbo;
+        if let Some(ref mut files) = self.shader_files {
+            files.watch()?;
+        }
+        self.resize(width, height);
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: u32, _time: Duration) {
+        self.reload_shaders();
+        draw_cube(frame, self.aspect, &self.uniforms);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        resize_viewport(width, height);
+        self.aspect = width as f32 / height as f32;
+    }
+
+    fn teardown(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.vbo) };
+        *self = CubeSmooth::default();
+    }
+}
+
+pub fn resize_viewport(width: u32, height: u32) {
+    unsafe { gl::Viewport(0, 0, width as i32, height as i32) };
+}
 
+fn build_program(vertex_source: &str, fragment_source: &str) -> Result<ShaderProgram> {
+    ShaderProgram::new(
+        vertex_source,
+        fragment_source,
+        &[(0, "in_position"), (1, "in_normal"), (2, "in_color")]
+    )
+}
+
+/// Builds the program and fills the vertex buffer, both are left bound.
+fn setup(vertex_source: &str, fragment_source: &str) -> Result<(ShaderProgram, GLuint)> {
     let colors: [f32; 12 * 6] = [
         // front
         0.0,  0.0,  1.0, // blue
@@ -117,102 +259,20 @@ pub fn init(
         1.0,  0.0,  1.0  // magenta
     ];
 
-    let normals: [f32; 12 * 6] = [
-        // front
-        0.0, 0.0, 1.0, // forward
-        0.0, 0.0, 1.0, // forward
-        0.0, 0.0, 1.0, // forward
-        0.0, 0.0, 1.0, // forward
-        // back
-        0.0, 0.0, -1.0, // backward
-        0.0, 0.0, -1.0, // backward
-        0.0, 0.0, -1.0, // backward
-        0.0, 0.0, -1.0, // backward
-        // right
-        1.0, 0.0, 0.0, // right
-        1.0, 0.0, 0.0, // right
-        1.0, 0.0, 0.0, // right
-        1.0, 0.0, 0.0, // right
-        // left
-        -1.0, 0.0, 0.0, // left
-        -1.0, 0.0, 0.0, // left
-        -1.0, 0.0, 0.0, // left
-        -1.0, 0.0, 0.0, // left
-        // top
-        0.0, 1.0, 0.0, // up
-        0.0, 1.0, 0.0, // up
-        0.0, 1.0, 0.0, // up
-        0.0, 1.0, 0.0, // up
-        // bottom
-        0.0, -1.0, 0.0, // down
-        0.0, -1.0, 0.0, // down
-        0.0, -1.0, 0.0, // down
-        0.0, -1.0, 0.0  // down
-    ];
-
-
-    let gl_program = egl::create_program(vertex_shader_source, fragment_shader_source);
-    assert!(gl_program >= 0);
-    let gl_program = gl_program as u32;
-
-    unsafe{
-        let s = CString::new("in_position").unwrap();
-        gl::BindAttribLocation(gl_program, 0, s.as_ptr())
-    };
-    unsafe{
-        let s = CString::new("in_normal").unwrap();
-        gl::BindAttribLocation(gl_program, 1, s.as_ptr())
-    };
-    unsafe{
-        let s = CString::new("in_color").unwrap();
-        gl::BindAttribLocation(gl_program, 2, s.as_ptr())
-    };
-
-    unsafe { gl::LinkProgram(gl_program) };
-
-    assert_ne!(unsafe {
-        let mut ret = 0;
-        gl::GetProgramiv(gl_program, gl::LINK_STATUS, &mut ret);
-        ret
-    }, 0);
-    // if ret == 0 {
-    //     char *log;
-
-    //     printf("program linking failed!:\n");
-    //     glGetProgramiv(program, GL_INFO_LOG_LENGTH, &ret);
-
-    //     if (ret > 1) {
-    //         log = malloc(ret);
-    //         glGetProgramInfoLog(program, ret, NULL, log);
-    //         printf("%s", log);
-    //         free(log);
-    //     }
-
-    //     return -1;
-    // }
+    let program = build_program(vertex_source, fragment_source)?;
 
-    unsafe { gl::UseProgram(gl_program) };
+    program.bind();
 
-    let gl_modelviewmatrix = unsafe {
-        let s = CString::new("modelviewMatrix").unwrap();
-        gl::GetUniformLocation(gl_program, s.as_ptr())
-    };
-    let gl_modelviewprojectionmatrix = unsafe {
-        let s = CString::new("modelviewprojectionMatrix").unwrap();
-        gl::GetUniformLocation(gl_program, s.as_ptr())
-    };
-    let gl_normalmatrix = unsafe {
-        let s = CString::new("normalMatrix").unwrap();
-        gl::GetUniformLocation(gl_program, s.as_ptr())
-    };
-
-    unsafe { gl::Viewport(0, 0, mode.size().0 as i32, mode.size().1 as i32) };
-    unsafe { gl::Enable(gl::CULL_FACE) };
+    unsafe {
+        gl::Enable(gl::CULL_FACE);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::DepthFunc(gl::LEQUAL);
+    }
 
     let gl_positionsoffset = 0;
     let size_of = std::mem::size_of::<f32>();
-    let gl_colorsoffset = vertices.len() * size_of;
-    let gl_normalsoffset = (vertices.len() + colors.len()) * size_of;
+    let gl_colorsoffset = VERTICES.len() * size_of;
+    let gl_normalsoffset = (VERTICES.len() + colors.len()) * size_of;
     let mut gl_vbo = 0;
 
     unsafe {
@@ -220,17 +280,17 @@ pub fn init(
         gl::BindBuffer(gl::ARRAY_BUFFER, gl_vbo);
         gl::BufferData(
             gl::ARRAY_BUFFER,
-            (vertices.len() * size_of +
+            (VERTICES.len() * size_of +
                 colors.len() * size_of +
-                normals.len() * size_of) as isize,
+                NORMALS.len() * size_of) as isize,
             std::ptr::null(),
             gl::STATIC_DRAW
         );
         gl::BufferSubData(
             gl::ARRAY_BUFFER,
             gl_positionsoffset,
-            (vertices.len() * size_of) as isize,
-            vertices.as_ptr() as *const _
+            (VERTICES.len() * size_of) as isize,
+            VERTICES.as_ptr() as *const _
         );
         gl::BufferSubData(
             gl::ARRAY_BUFFER,
@@ -241,8 +301,8 @@ pub fn init(
         gl::BufferSubData(
             gl::ARRAY_BUFFER,
             gl_normalsoffset as isize,
-            (normals.len() * size_of) as isize,
-            normals.as_ptr() as *const _
+            (NORMALS.len() * size_of) as isize,
+            NORMALS.as_ptr() as *const _
         );
         gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, gl_positionsoffset as *const _);
         gl::EnableVertexAttribArray(0);
@@ -252,19 +312,16 @@ pub fn init(
         gl::EnableVertexAttribArray(2);
     };
 
-    (result_d, result_s, gl_modelviewmatrix, gl_modelviewprojectionmatrix, gl_normalmatrix)
+    Ok((program, gl_vbo))
 }
 
-pub fn draw(
-    i: u32,
-    aspect: f32,
-    modelviewmatrix: i32,
-    modelviewprojectionmatrix: i32,
-    normalmatrix: i32
-) {
-    /* clear the color buffer */
+/// Draws the spinning cube with the current program and vertex attributes, `aspect` is
+/// width over height.
+pub fn draw_cube(i: u32, aspect: f32, uniforms: &MatrixUniforms) {
+    /* clear the color and depth buffers */
     unsafe { gl::ClearColor(0.0, 0.5, 0.5, 1.0) };
-    unsafe { gl::Clear(gl::COLOR_BUFFER_BIT) };
+    unsafe { gl::ClearDepthf(1.0) };
+    unsafe { gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT) };
 
     let modelview = ESMatrix::identity()
         .translate(0.0, 0.0, -8.0)
@@ -272,29 +329,18 @@ pub fn draw(
         .rotate(45.0 - (0.5 * i as f32), 0.0, 1.0, 0.0)
         .rotate(10.0 + (0.15 * i as f32), 0.0, 0.0, 1.0);
 
-    let projection = ESMatrix::identity()
-        .frustum(-2.8, 2.8, -2.8 * aspect, 2.8 * aspect, 6.0, 10.0);
+    let projection = ESMatrix::identity().perspective(fovy(aspect), aspect, 6.0, 10.0);
 
     let modelviewprojection = ESMatrix::multiply(modelview, projection);
 
-    let normal = [
-        modelview.m()[0][0],
-        modelview.m()[0][1],
-        modelview.m()[0][2],
-        modelview.m()[1][0],
-        modelview.m()[1][1],
-        modelview.m()[1][2],
-        modelview.m()[2][0],
-        modelview.m()[2][1],
-        modelview.m()[2][2],
-    ];
+    let normal = modelview.normal_matrix();
 
     let a = modelview.l();
     let b = modelviewprojection.l();
 
-    unsafe { gl::UniformMatrix4fv(modelviewmatrix, 1, gl::FALSE, a.as_ptr()) };
-    unsafe { gl::UniformMatrix4fv(modelviewprojectionmatrix, 1, gl::FALSE, b.as_ptr()) };
-    unsafe { gl::UniformMatrix3fv(normalmatrix, 1, gl::FALSE, normal.as_ptr()) };
+    unsafe { gl::UniformMatrix4fv(uniforms.modelview, 1, gl::FALSE, a.as_ptr()) };
+    unsafe { gl::UniformMatrix4fv(uniforms.modelviewprojection, 1, gl::FALSE, b.as_ptr()) };
+    unsafe { gl::UniformMatrix3fv(uniforms.normal, 1, gl::FALSE, normal.as_ptr()) };
 
     unsafe {
         gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
@@ -0,0 +1,237 @@
+use std::time::Duration;
+
+use gbm::Format;
+use sys::gles2 as gl;
+use sys::gles2::types::GLuint;
+
+use crate::cube_smooth::{self, MatrixUniforms, NORMALS, VERTICES};
+use crate::error::Result;
+use crate::renderer::Renderer;
+use crate::shader::ShaderProgram;
+
+const TEXTURE_SIZE: usize = 256;
+/// Edge length of the checkerboard squares in texels.
+const CHECKER_SIZE: usize = 32;
+
+/// Fills an RGBA texture with a checkerboard alternating between a color gradient and white,
+/// so both orientation and filtering are visible on the cube.
+fn generate_texture() -> Vec<u8> {
+    let mut texels = Vec::with_capacity(TEXTURE_SIZE * TEXTURE_SIZE * 4);
+
+    for y in 0..TEXTURE_SIZE {
+        for x in 0..TEXTURE_SIZE {
+            if (x / CHECKER_SIZE + y / CHECKER_SIZE) % 2 == 0 {
+                texels.extend_from_slice(&[x as u8, y as u8, (TEXTURE_SIZE - 1 - x) as u8, 0xff]);
+            } else {
+                texels.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+            }
+        }
+    }
+
+    texels
+}
+
+/// Cube with a generated RGBA texture on each face, lit like the smooth cube.
+#[derive(Default)]
+pub struct CubeTex {
+    program: Option<ShaderProgram>,
+    vbo: GLuint,
+    texture: GLuint,
+    uniforms: MatrixUniforms,
+    aspect: f32,
+}
+
+impl Renderer for CubeTex {
+    fn init(&mut self, width: u32, height: u32, _format: Format) -> Result<()> {
+        let (program, vbo, texture) = setup()?;
+
+        self.uniforms = MatrixUniforms::locate(&program);
+        self.program = Some(program);
+        self.vbo = vbo;
+        self.texture = texture;
+        self.resize(width, height);
+
+        Ok(())
+    }
+
+    /// The geometry and animation are the ones of the smooth cube, only the program bound in
+    /// `init` differs.
+    fn draw(&mut self, frame: u32, _time: Duration) {
+        cube_smooth::draw_cube(frame, self.aspect, &self.uniforms);
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        cube_smooth::resize_viewport(width, height);
+        self.aspect = width as f32 / height as f32;
+    }
+
+    fn teardown(&mut self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+        *self = CubeTex::default();
+    }
+}
+
+/// Builds the program, fills the vertex buffer and uploads the texture, all are left bound.
+fn setup() -> Result<(ShaderProgram, GLuint, GLuint)> {
+    let vertex_shader_source = r#"
+        uniform mat4 modelviewMatrix;
+        uniform mat4 modelviewprojectionMatrix;
+        uniform mat3 normalMatrix;
+
+        attribute vec4 in_position;
+        attribute vec3 in_normal;
+        attribute vec2 in_texcoord;
+
+        vec4 lightSource = vec4(2.0, 2.0, 20.0, 0.0);
+
+        varying float vDiffuse;
+        varying vec2 vTexCoord;
+
+        void main()
+        {
+            gl_Position = modelviewprojectionMatrix * in_position;
+            vec3 vEyeNormal = normalMatrix * in_normal;
+            vec4 vPosition4 = modelviewMatrix * in_position;
+            vec3 vPosition3 = vPosition4.xyz / vPosition4.w;
+            vec3 vLightDir = normalize(lightSource.xyz - vPosition3);
+            vDiffuse = max(0.0, dot(vEyeNormal, vLightDir));
+            vTexCoord = in_texcoord;
+        }
+    "#;
+
+    let fragment_shader_source = r#"
+        precision mediump float;
+
+        uniform sampler2D uTexture;
+
+        varying float vDiffuse;
+        varying vec2 vTexCoord;
+
+        void main()
+        {
+            gl_FragColor = vec4(vDiffuse * texture2D(uTexture, vTexCoord).rgb, 1.0);
+        }
+    "#;
+
+    let texcoords: [f32; 8 * 6] = [
+        // front
+        0.0, 0.0,
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0,
+        // back
+        0.0, 0.0,
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0,
+        // right
+        0.0, 0.0,
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0,
+        // left
+        0.0, 0.0,
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0,
+        // top
+        0.0, 0.0,
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0,
+        // bottom
+        0.0, 0.0,
+        1.0, 0.0,
+        0.0, 1.0,
+        1.0, 1.0
+    ];
+
+    let program = ShaderProgram::new(
+        vertex_shader_source,
+        fragment_shader_source,
+        &[(0, "in_position"), (1, "in_normal"), (2, "in_texcoord")]
+    )?;
+
+    program.bind();
+
+    let gl_texture = program.uniform_location("uTexture");
+
+    unsafe {
+        gl::Enable(gl::CULL_FACE);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::DepthFunc(gl::LEQUAL);
+    }
+
+    let texels = generate_texture();
+    let mut gl_tex = 0;
+
+    unsafe {
+        gl::ActiveTexture(gl::TEXTURE0);
+        gl::GenTextures(1, &mut gl_tex);
+        gl::BindTexture(gl::TEXTURE_2D, gl_tex);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            gl::RGBA as i32,
+            TEXTURE_SIZE as i32,
+            TEXTURE_SIZE as i32,
+            0,
+            gl::RGBA,
+            gl::UNSIGNED_BYTE,
+            texels.as_ptr() as *const _
+        );
+        gl::Uniform1i(gl_texture, 0);
+    }
+
+    let gl_positionsoffset = 0;
+    let size_of = std::mem::size_of::<f32>();
+    let gl_texcoordsoffset = VERTICES.len() * size_of;
+    let gl_normalsoffset = (VERTICES.len() + texcoords.len()) * size_of;
+    let mut gl_vbo = 0;
+
+    unsafe {
+        gl::GenBuffers(1, &mut gl_vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, gl_vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            (VERTICES.len() * size_of +
+                texcoords.len() * size_of +
+                NORMALS.len() * size_of) as isize,
+            std::ptr::null(),
+            gl::STATIC_DRAW
+        );
+        gl::BufferSubData(
+            gl::ARRAY_BUFFER,
+            gl_positionsoffset,
+            (VERTICES.len() * size_of) as isize,
+            VERTICES.as_ptr() as *const _
+        );
+        gl::BufferSubData(
+            gl::ARRAY_BUFFER,
+            gl_texcoordsoffset as isize,
+            (texcoords.len() * size_of) as isize,
+            texcoords.as_ptr() as *const _
+        );
+        gl::BufferSubData(
+            gl::ARRAY_BUFFER,
+            gl_normalsoffset as isize,
+            (NORMALS.len() * size_of) as isize,
+            NORMALS.as_ptr() as *const _
+        );
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, 0, gl_positionsoffset as *const _);
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, 0, gl_normalsoffset as *const _);
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(2, 2, gl::FLOAT, gl::FALSE, 0, gl_texcoordsoffset as *const _);
+        gl::EnableVertexAttribArray(2);
+    };
+
+    Ok((program, gl_vbo, gl_tex))
+}
@@ -0,0 +1,214 @@
+//! The KMS display pipeline: opening the device, setting up GBM, EGL and the CRTC, and the
+//! frame loop driving a renderer.
+
+use std::time::Instant;
+
+use sys::Card;
+
+use gbm::{Device, BufferObjectFlags, Format, Modifier};
+
+use crate::capture;
+use crate::config::{self, DisplayConfig};
+use crate::drm_atomic::DrmAtomic;
+use crate::drm_common::{self, Drm};
+use crate::drm_fb::{self, DrmFb};
+use crate::drm_legacy;
+use crate::egl::{self, Egl};
+use crate::msaa;
+use crate::error::{Error, Result};
+use crate::perf::Perf;
+use crate::renderer::{Renderer, TeardownGuard};
+use crate::shutdown;
+
+/// Opens the DRM device from `config` and creates a GBM device on it.
+pub fn open(config: &DisplayConfig) -> Result<Device<Card>> {
+    let card = Card::open(&config.device)
+        .map_err(|source| Error::DeviceOpen { path: config.device.clone(), source })?;
+
+    Device::new(card).map_err(Error::Gbm)
+}
+
+/// Sets up the output selected in `config` and runs the frame loop until `stop` returns true
+/// for the number of the next frame, then restores the previous configuration.
+///
+/// `renderer` is initialized once the GL context exists and torn down before it goes away,
+/// also when setup or the loop fails.
+pub fn run(
+    gbm: &Device<Card>,
+    config: &DisplayConfig,
+    renderer: &mut dyn Renderer,
+    stop: &mut dyn FnMut(u32) -> bool
+) -> Result<()> {
+    let drm = drm_common::get_resources(gbm, config)?;
+    let mode = drm.mode;
+
+    let (egl, pixel_format) = match select_format(gbm, &drm, config.format) {
+        Format::Xrgb8888 => (egl::init(gbm, config.samples, Format::Xrgb8888)?, Format::Xrgb8888),
+        format => match egl::init(gbm, config.samples, format) {
+            Ok(egl) => (egl, format),
+            Err(e) => {
+                println!("no EGL config for {} ({}), falling back to XR24", config::format_name(format), e);
+                (egl::init(gbm, config.samples, Format::Xrgb8888)?, Format::Xrgb8888)
+            }
+        },
+    };
+    let modifiers = select_modifiers(gbm, &drm, &egl, config, pixel_format);
+    let gbm_surface = create_surface(gbm, &drm, config, pixel_format, &modifiers)?;
+    // declared after the GBM surface so it's dropped first, with the EGL surface made from it
+    let mut egl = egl;
+
+    let restore = shutdown::save_crtc(drm_common::SavedCrtc::new(gbm, &drm)?);
+
+    egl.create_window_surface(&gbm_surface)?;
+
+    let msaa = if egl.samples() > 0 {
+        None
+    } else {
+        msaa::create(mode.size().0 as u32, mode.size().1 as u32, config.samples, egl.has_depth(), pixel_format)
+    };
+
+    // also torn down if init fails halfway
+    let mut renderer = TeardownGuard::new(renderer);
+    renderer.init(mode.size().0 as u32, mode.size().1 as u32, pixel_format)?;
+
+    let start = Instant::now();
+
+    let mut draw = |i| {
+        if let Some(ref msaa) = msaa {
+            msaa.bind();
+        }
+        renderer.draw(i, start.elapsed());
+        if let Some(ref msaa) = msaa {
+            msaa.resolve();
+        }
+    };
+    // captures and soak reports, run by the loops outside the draw timings
+    let mut after_draw = |i| {
+        if config.soak && i > 0 && i % 1000 == 0 {
+            drm_fb::print_soak_report("running", i);
+        }
+        if let Some(ref capture) = config.capture {
+            if capture.wants(i) {
+                capture::save_frame(capture, i, mode.size().0 as u32, mode.size().1 as u32, pixel_format);
+            }
+        }
+    };
+
+    let atomic = if config.atomic {
+        match DrmAtomic::init(gbm, &drm) {
+            Ok(atomic) => Some(atomic),
+            Err(e) => {
+                println!("no atomic modesetting support ({}), falling back to legacy", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut perf = Perf::new(config);
+    let frames = match atomic {
+        Some(atomic) => atomic.run(gbm, &drm, &gbm_surface, &egl, stop, &mut perf, &mut draw, &mut after_draw),
+        None => drm_legacy::run(gbm, &drm, &gbm_surface, &egl, stop, &mut perf, &mut draw, &mut after_draw),
+    }?;
+
+    perf.finish();
+    drop(renderer);
+
+    if config.soak {
+        drm_fb::print_soak_report("before teardown", frames);
+    }
+
+    // the GL objects go while the context exists, the EGL surface next as it renders into
+    // the GBM surface, the previous CRTC configuration goes back while our framebuffers still
+    // exist, then destroying the GBM surface frees its buffer objects and the framebuffers
+    // attached to them
+    drop(msaa);
+    drop(egl);
+    drop(restore);
+    drop(gbm_surface);
+
+    if config.soak {
+        drm_fb::print_soak_report("after teardown", frames);
+    }
+
+    Ok(())
+}
+
+/// Returns `format` if the primary plane can scan it out and GBM can allocate it for
+/// scanout, XRGB8888 otherwise.
+fn select_format(gbm: &Device<Card>, drm: &Drm, format: Format) -> Format {
+    if format == Format::Xrgb8888 {
+        return format;
+    }
+
+    // without universal planes the plane list can't be read, GBM gets the last word then
+    let on_plane = drm_common::scanout_formats(gbm, drm.crtc.handle())
+        .map_or(true, |formats| formats.contains(&(format as u32)));
+    let reason = if !on_plane {
+        "the primary plane can't scan it out"
+    } else if !gbm.is_format_supported(format, BufferObjectFlags::SCANOUT | BufferObjectFlags::RENDERING) {
+        "GBM can't allocate it for scanout"
+    } else {
+        return format;
+    };
+
+    println!("format {} is not supported: {}, falling back to XR24", config::format_name(format), reason);
+    Format::Xrgb8888
+}
+
+/// Picks the modifiers the GBM surface may be allocated with: the one forced in `config`, else
+/// those both the primary plane and the renderer support.
+///
+/// Empty if neither side advertises any, leaving the layout to the driver.
+fn select_modifiers(gbm: &Device<Card>, drm: &Drm, egl: &Egl, config: &DisplayConfig, format: Format) -> Vec<u64> {
+    let forced = if config.linear { Some(u64::from(Modifier::Linear)) } else { config.modifier };
+
+    let scanout = match drm_common::scanout_modifiers(gbm, drm.crtc.handle(), format as u32) {
+        Ok(modifiers) => modifiers,
+        Err(e) => {
+            println!("failed to read the plane's IN_FORMATS ({}), using implicit modifiers", e);
+            Vec::new()
+        }
+    };
+
+    if let Some(modifier) = forced {
+        if !scanout.is_empty() && !scanout.contains(&modifier) {
+            println!("modifier 0x{:x} is not advertised by the plane, trying anyway", modifier);
+        }
+        return vec![modifier];
+    }
+
+    match egl.render_modifiers(format) {
+        Some(render) => scanout.into_iter().filter(|modifier| render.contains(modifier)).collect(),
+        None => scanout,
+    }
+}
+
+/// Creates the scanout surface with an explicit modifier list if there is one, falling back
+/// to usage flags when GBM can't allocate with modifiers.
+fn create_surface(
+    gbm: &Device<Card>,
+    drm: &Drm,
+    config: &DisplayConfig,
+    format: Format,
+    modifiers: &[u64]
+) -> Result<gbm::Surface<DrmFb>> {
+    let (width, height) = (drm.mode.size().0 as u32, drm.mode.size().1 as u32);
+
+    if !modifiers.is_empty() {
+        let list = modifiers.iter().map(|modifier| format!("0x{:x}", modifier)).collect::<Vec<_>>();
+        println!("using modifiers {}", list.join(", "));
+
+        match gbm.create_surface_with_modifiers(width, height, format,
+            modifiers.iter().map(|&modifier| Modifier::from(modifier))) {
+            Ok(surface) => return Ok(surface),
+            Err(e) => println!("failed to create a surface with modifiers ({}), falling back to usage flags", e),
+        }
+    }
+
+    let usage = BufferObjectFlags::SCANOUT | BufferObjectFlags::RENDERING;
+    let usage = if config.linear { usage | BufferObjectFlags::LINEAR } else { usage };
+
+    gbm.create_surface(width, height, format, usage).map_err(Error::Gbm)
+}
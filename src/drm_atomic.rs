@@ -0,0 +1,153 @@
+use sys::Card;
+
+use std::collections::HashMap;
+use std::io;
+use std::time::Instant;
+
+use drm::{ClientCapability, Device as DrmDevice};
+use drm::control::{framebuffer, plane, property, AtomicCommitFlags, Device as ControlDevice, ResourceHandle};
+use drm::control::atomic::AtomicModeReq;
+use drm::control::property::Value;
+use gbm::Device;
+
+use crate::drm_common::{self, Drm};
+use crate::drm_fb::{self, DrmFb};
+use crate::egl::Egl;
+use crate::error::{Error, Result};
+use crate::perf::Perf;
+
+type Properties = HashMap<String, property::Handle>;
+
+pub struct DrmAtomic {
+    card: Card,
+    plane: plane::Handle,
+    mode_blob: u64,
+    connector_props: Properties,
+    crtc_props: Properties,
+    plane_props: Properties,
+}
+
+impl DrmAtomic {
+    /// Enables atomic modesetting on the device and looks up the primary plane and the
+    /// properties needed for the commits.
+    ///
+    /// Fails if the driver refuses `DRM_CLIENT_CAP_ATOMIC`, the caller is expected to fall
+    /// back to the legacy path in that case.
+    pub fn init(card: &Card, drm: &Drm) -> Result<Self> {
+        card.set_client_capability(ClientCapability::Atomic, true).map_err(Error::Drm)?;
+
+        let plane = drm_common::find_primary_plane(card, drm.crtc.handle()).map_err(Error::Drm)?;
+        println!("Using plane {} for crtc {}", u32::from(plane), u32::from(drm.crtc.handle()));
+
+        let connector_props = load_properties(card, drm.connector.handle()).map_err(Error::Drm)?;
+        let crtc_props = load_properties(card, drm.crtc.handle()).map_err(Error::Drm)?;
+        let plane_props = load_properties(card, plane).map_err(Error::Drm)?;
+
+        // cloned before the blob exists, so nothing is left behind if it fails
+        let card = card.try_clone().map_err(Error::DupFd)?;
+        let mode_blob = u64::from(card.create_property_blob(&drm.mode).map_err(Error::Drm)?);
+
+        Ok(DrmAtomic {
+            card,
+            plane,
+            mode_blob,
+            connector_props,
+            crtc_props,
+            plane_props,
+        })
+    }
+
+    fn commit(&self, drm: &Drm, fb: framebuffer::Handle, flags: AtomicCommitFlags) -> io::Result<()> {
+        let crtc = drm.crtc.handle();
+        let (width, height) = (drm.mode.size().0 as u64, drm.mode.size().1 as u64);
+
+        let mut req = AtomicModeReq::new();
+
+        if flags.contains(AtomicCommitFlags::ALLOW_MODESET) {
+            req.add_property(drm.connector.handle(), property(&self.connector_props, "CRTC_ID")?,
+                Value::CRTC(Some(crtc)));
+            req.add_property(crtc, property(&self.crtc_props, "MODE_ID")?, Value::Blob(self.mode_blob));
+            req.add_property(crtc, property(&self.crtc_props, "ACTIVE")?, Value::Boolean(true));
+        }
+
+        let plane = |name| property(&self.plane_props, name);
+        req.add_property(self.plane, plane("FB_ID")?, Value::Framebuffer(Some(fb)));
+        req.add_property(self.plane, plane("CRTC_ID")?, Value::CRTC(Some(crtc)));
+        req.add_property(self.plane, plane("SRC_X")?, Value::UnsignedRange(0));
+        req.add_property(self.plane, plane("SRC_Y")?, Value::UnsignedRange(0));
+        req.add_property(self.plane, plane("SRC_W")?, Value::UnsignedRange(width << 16));
+        req.add_property(self.plane, plane("SRC_H")?, Value::UnsignedRange(height << 16));
+        req.add_property(self.plane, plane("CRTC_X")?, Value::SignedRange(0));
+        req.add_property(self.plane, plane("CRTC_Y")?, Value::SignedRange(0));
+        req.add_property(self.plane, plane("CRTC_W")?, Value::UnsignedRange(width));
+        req.add_property(self.plane, plane("CRTC_H")?, Value::UnsignedRange(height));
+
+        self.card.atomic_commit(flags, req)
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn run(
+        &self,
+        gbm: &Device<Card>,
+        drm: &Drm,
+        gbm_surface: &gbm::Surface<DrmFb>,
+        egl: &Egl,
+        stop: &mut dyn FnMut(u32) -> bool,
+        perf: &mut Perf,
+        draw: &mut dyn FnMut(u32),
+        after_draw: &mut dyn FnMut(u32)
+    ) -> Result<u32> {
+        // the first commit does the modeset and blocks, the rest are queued as page flips
+        let mut flags = AtomicCommitFlags::ALLOW_MODESET | AtomicCommitFlags::PAGE_FLIP_EVENT;
+        let mut bo = None;
+        let mut i = 0;
+
+        while !stop(i) {
+            let draw_start = Instant::now();
+            draw(i);
+            let draw_end = Instant::now();
+            // before the swap, which hands the back buffer over, but not timed as drawing
+            after_draw(i);
+            i += 1;
+
+            let swap_start = Instant::now();
+            egl.swap_buffers()?;
+            let swap_end = Instant::now();
+            let mut next_bo = unsafe { gbm_surface.lock_front_buffer() }.map_err(|_| Error::LockFrontBuffer)?;
+            let fb = drm_fb::get_from_bo(gbm, &mut next_bo)?;
+
+            self.commit(drm, fb, flags).map_err(Error::Drm)?;
+            flags = AtomicCommitFlags::NONBLOCK | AtomicCommitFlags::PAGE_FLIP_EVENT;
+
+            let flip = drm_common::wait_for_flip(gbm)?;
+            perf.record(draw_end - draw_start, swap_end - swap_start, Some(flip));
+
+            // release the previous buffer only once the new one is on screen
+            bo = Some(next_bo);
+        }
+
+        drop(bo);
+
+        Ok(i)
+    }
+}
+
+impl Drop for DrmAtomic {
+    fn drop(&mut self) {
+        let _ = self.card.destroy_property_blob(self.mode_blob);
+    }
+}
+
+fn property(props: &Properties, name: &str) -> io::Result<property::Handle> {
+    props.get(name).cloned().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("no {} property", name))
+    })
+}
+
+fn load_properties<H: ResourceHandle>(card: &Card, handle: H) -> io::Result<Properties> {
+    Ok(card.get_properties(handle)?
+        .as_hashmap(card)?
+        .into_iter()
+        .map(|(name, info)| (name, info.handle()))
+        .collect())
+}
@@ -0,0 +1,245 @@
+use sys::{kms, Card};
+
+use std::io;
+use std::time::Duration;
+
+use drm::control::{Device as ControlDevice, connector::Info as ConnectorInfo, Mode, ModeTypeFlags,
+    ResourceHandles, crtc::Info as CrtcInfo, Events, Event};
+use drm::ClientCapability;
+use drm::control::{connector, crtc, framebuffer, plane, PlaneType, ResourceHandle};
+
+use crate::error::{Error, Result};
+use crate::config::{ConnectorSelector, DisplayConfig, VideoMode};
+
+/// KMS objects the cube is displayed on.
+pub struct Drm {
+    pub connector: ConnectorInfo,
+    pub mode: Mode,
+    pub crtc: CrtcInfo,
+    pub crtc_index: usize,
+}
+
+pub fn get_resources(card: &Card, config: &DisplayConfig) -> Result<Drm> {
+    let resources = card.resource_handles().map_err(Error::Drm)?;
+
+    let connector = find_connector(card, &resources, config.connector.as_ref())?;
+    let mode = find_mode(&connector, config.video_mode.as_ref())?;
+
+    println!("size {:?}, clock {:?}, hsync {:?}, vsync {:?}, hskew {:?}, vscan {:?}, vrefresh {:?}, pref {}, {}",
+        mode.size(), mode.clock(), mode.hsync(), mode.vsync(), mode.hskew(),
+        mode.vscan(), mode.vrefresh(), is_preferred(&mode), mode.name().to_string_lossy().into_owned());
+
+    let crtc_handle = find_crtc(card, &resources, &connector)?;
+    let crtc = card.get_crtc(crtc_handle).map_err(Error::Drm)?;
+    let crtc_index = resources.crtcs().iter().position(|&c| c == crtc_handle).ok_or(Error::NoCrtc)?;
+
+    println!("using connector {}, crtc {}", connector_name(&connector), crtc_index);
+
+    Ok(Drm {
+        connector,
+        mode,
+        crtc,
+        crtc_index,
+    })
+}
+
+/// CRTC configuration found at startup.
+pub struct SavedCrtc {
+    card: Card,
+    crtc: crtc::Handle,
+    fb: Option<framebuffer::Handle>,
+    position: (u32, u32),
+    mode: Option<Mode>,
+    connectors: Vec<connector::Handle>,
+}
+
+impl SavedCrtc {
+    /// `drm.crtc` is loaded before anything is set, so it still describes what was there.
+    pub fn new(card: &Card, drm: &Drm) -> Result<Self> {
+        let mode = drm.crtc.mode();
+
+        Ok(SavedCrtc {
+            card: card.try_clone().map_err(Error::DupFd)?,
+            crtc: drm.crtc.handle(),
+            fb: drm.crtc.framebuffer(),
+            position: drm.crtc.position(),
+            mode,
+            // a CRTC without a mode was off, setting it without connectors keeps it that way
+            connectors: if mode.is_some() { vec![drm.connector.handle()] } else { Vec::new() },
+        })
+    }
+
+    pub fn restore(&self) -> Result<()> {
+        self.card.set_crtc(self.crtc, self.fb, self.position, &self.connectors, self.mode).map_err(Error::Drm)?;
+        Ok(())
+    }
+}
+
+/// Name of the connector as the kernel reports it, e.g. `HDMI-A-1`.
+pub fn connector_name(connector: &ConnectorInfo) -> String {
+    format!("{}-{}", connector.interface().as_str(), connector.interface_id())
+}
+
+/// Without a selector the first connected connector reporting a physical size is used.
+fn find_connector(card: &Card, resources: &ResourceHandles, selector: Option<&ConnectorSelector>)
+    -> Result<ConnectorInfo>
+{
+    // probed like libdrm's drmModeGetConnector does, the state may be stale otherwise
+    let load = |handle| card.get_connector(handle, true);
+    let connectors = || resources.connectors().iter().filter_map(|&c| load(c).ok());
+    let connected = |c: &ConnectorInfo| c.state() == connector::State::Connected;
+
+    let selector = match selector {
+        Some(selector) => selector,
+        None => return connectors()
+            .find(|c| connected(c) && c.size().map_or(false, |(width, height)| width > 0 && height > 0))
+            .ok_or(Error::NoConnector),
+    };
+
+    let connector = match *selector {
+        ConnectorSelector::Index(index) => match resources.connectors().get(index) {
+            Some(&handle) => Some(load(handle).map_err(Error::Drm)?),
+            None => None,
+        },
+        ConnectorSelector::Name(ref name) => connectors().find(|c| connector_name(c) == *name),
+    }.ok_or_else(|| Error::ConnectorNotFound(selector.clone()))?;
+
+    if !connected(&connector) {
+        return Err(Error::ConnectorDisconnected(connector_name(&connector)));
+    }
+
+    Ok(connector)
+}
+
+fn is_preferred(mode: &Mode) -> bool {
+    mode.mode_type().contains(ModeTypeFlags::PREFERRED)
+}
+
+fn mode_area(mode: &Mode) -> u32 {
+    mode.size().0 as u32 * mode.size().1 as u32
+}
+
+/// Without a requested mode the one flagged as preferred wins, then the largest.
+fn find_mode(connector: &ConnectorInfo, video_mode: Option<&VideoMode>) -> Result<Mode> {
+    let modes = connector.modes();
+
+    let mode = match video_mode {
+        Some(video_mode) => modes.iter()
+            .filter(|m| {
+                let name = m.name().to_string_lossy();
+                let size = format!("{}x{}", m.size().0, m.size().1);
+                (name == video_mode.name || size == video_mode.name) &&
+                    video_mode.vrefresh.map_or(true, |vrefresh| m.vrefresh() == vrefresh)
+            })
+            .max_by_key(|m| m.vrefresh())
+            .ok_or_else(|| Error::NoMode(video_mode.clone()))?,
+        None => modes.iter()
+            .find(|m| is_preferred(m))
+            .or_else(|| modes.iter().max_by_key(|m| (mode_area(m), m.vrefresh())))
+            .ok_or_else(|| Error::NoModes(connector_name(connector)))?,
+    };
+
+    Ok(*mode)
+}
+
+/// Returns the CRTC currently driving the connector. A cold booted display has no encoder or
+/// CRTC bound yet, then the first CRTC one of its encoders can drive and no other encoder is
+/// using is picked.
+fn find_crtc(card: &Card, resources: &ResourceHandles, connector: &ConnectorInfo) -> Result<crtc::Handle> {
+    if let Some(encoder) = connector.current_encoder() {
+        if let Some(crtc) = card.get_encoder(encoder).map_err(Error::Drm)?.crtc() {
+            return Ok(crtc);
+        }
+    }
+
+    if connector.encoders().is_empty() {
+        return Err(Error::NoEncoder);
+    }
+
+    let busy: Vec<crtc::Handle> = resources.encoders().iter()
+        .filter_map(|&e| card.get_encoder(e).ok())
+        .filter_map(|e| e.crtc())
+        .collect();
+
+    connector.encoders().iter()
+        .filter_map(|&e| card.get_encoder(e).ok())
+        .flat_map(|e| resources.filter_crtcs(e.possible_crtcs()))
+        .find(|crtc| !busy.contains(crtc))
+        .ok_or(Error::NoCrtc)
+}
+
+/// Current value of the property `name` of a KMS object, `None` if it has no such property.
+fn property_value<T: ControlDevice, H: ResourceHandle>(device: &T, handle: H, name: &str) -> io::Result<Option<u64>> {
+    for (&prop, &value) in &device.get_properties(handle)? {
+        if device.get_property(prop)?.name().to_bytes() == name.as_bytes() {
+            return Ok(Some(value));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns the primary plane which can be attached to `crtc`.
+///
+/// Primary planes are only listed once `DRM_CLIENT_CAP_UNIVERSAL_PLANES` is set, which
+/// `DRM_CLIENT_CAP_ATOMIC` implies.
+pub fn find_primary_plane<T: ControlDevice>(device: &T, crtc: crtc::Handle) -> io::Result<plane::Handle> {
+    let resources = device.resource_handles()?;
+
+    for plane in device.plane_handles()? {
+        let info = device.get_plane(plane)?;
+        if !resources.filter_crtcs(info.possible_crtcs()).contains(&crtc) {
+            continue;
+        }
+
+        if property_value(device, plane, "type")? == Some(PlaneType::Primary as u64) {
+            return Ok(plane);
+        }
+    }
+
+    Err(io::Error::new(io::ErrorKind::NotFound, "no primary plane for the crtc"))
+}
+
+/// Formats the primary plane of the CRTC can scan out.
+pub fn scanout_formats<T: ControlDevice>(device: &T, crtc: crtc::Handle) -> io::Result<Vec<u32>> {
+    device.set_client_capability(ClientCapability::UniversalPlanes, true)?;
+    let plane = find_primary_plane(device, crtc)?;
+
+    Ok(device.get_plane(plane)?.formats().to_vec())
+}
+
+/// Modifiers the primary plane of the CRTC can scan out `format` with, read from its
+/// IN_FORMATS property.
+///
+/// Empty if the driver doesn't expose the property, in which case only implicit modifiers
+/// work.
+pub fn scanout_modifiers<T: ControlDevice>(device: &T, crtc: crtc::Handle, format: u32) -> io::Result<Vec<u64>> {
+    device.set_client_capability(ClientCapability::UniversalPlanes, true)?;
+    let plane = find_primary_plane(device, crtc)?;
+
+    match property_value(device, plane, "IN_FORMATS")? {
+        Some(blob) if blob != 0 => kms::parse_in_formats(&device.get_property_blob(blob)?, format),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// Blocks until the pending page flip has completed, returns the timestamp of the flip.
+pub fn wait_for_flip<T: ControlDevice>(device: &T) -> Result<Duration> {
+    let mut events: Events;
+    let mut flipped = None;
+    while flipped.is_none() {
+        events = device.receive_events().map_err(Error::Drm)?;
+        for event in events {
+            match event {
+                Event::Vblank(_s) => {}, //println!("VblankEvent:{}", s.frame),
+                Event::PageFlip(s) => {
+                    // println!("PageFlipEvent:{}", s.frame);
+                    flipped = Some(s.duration);
+                }
+                Event::Unknown(_s) => {}, //println!("unkonw event:{:?}", s),
+            }
+        }
+    }
+
+    Ok(flipped.unwrap_or_default())
+}
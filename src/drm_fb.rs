@@ -0,0 +1,132 @@
+use sys::Card;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use drm::buffer::{self, PlanarBuffer};
+use drm::control::{self, framebuffer, Device as ControlDevice, FbCmd2Flags};
+use drm::{Device as DrmDevice, DriverCapability};
+use gbm::{BufferObject, Device, Format, Modifier};
+
+use crate::error::{Error, Result};
+
+static CREATED: AtomicUsize = AtomicUsize::new(0);
+static ALIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// KMS framebuffer stored as the user data of the GBM buffer object it wraps.
+///
+/// GBM drops the user data when it destroys the buffer object, which removes the framebuffer
+/// along with it.
+pub struct DrmFb {
+    card: Card,
+    handle: framebuffer::Handle,
+}
+
+impl Drop for DrmFb {
+    fn drop(&mut self) {
+        let _ = self.card.destroy_framebuffer(self.handle);
+        ALIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// The planes of a buffer object as ADDFB2 takes them. The modifier is only passed when the
+/// driver accepts explicit ones.
+struct Planes {
+    size: (u32, u32),
+    format: Format,
+    modifier: Option<Modifier>,
+    pitches: [u32; 4],
+    handles: [Option<buffer::Handle>; 4],
+    offsets: [u32; 4],
+}
+
+impl PlanarBuffer for Planes {
+    fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    fn format(&self) -> Format {
+        self.format
+    }
+
+    fn modifier(&self) -> Option<Modifier> {
+        self.modifier
+    }
+
+    fn pitches(&self) -> [u32; 4] {
+        self.pitches
+    }
+
+    fn handles(&self) -> [Option<buffer::Handle>; 4] {
+        self.handles
+    }
+
+    fn offsets(&self) -> [u32; 4] {
+        self.offsets
+    }
+}
+
+/// Returns the framebuffer of `bo`, creating it the first time the buffer comes out of the
+/// surface's swapchain.
+pub fn get_from_bo(gbm: &Device<Card>, bo: &mut BufferObject<DrmFb>) -> Result<framebuffer::Handle> {
+    if let Ok(Some(fb)) = bo.userdata() {
+        return Ok(fb.handle);
+    }
+
+    let mut planes = Planes {
+        size: (bo.width()?, bo.height()?),
+        format: bo.format()?,
+        modifier: None,
+        pitches: [0; 4],
+        handles: [None; 4],
+        offsets: [0; 4],
+    };
+
+    for plane in 0..(bo.plane_count()? as usize).min(4) {
+        planes.handles[plane] = control::from_u32(unsafe { bo.handle_for_plane(plane as i32)?.u32_ });
+        planes.pitches[plane] = bo.stride_for_plane(plane as i32)?;
+        planes.offsets[plane] = bo.offset(plane as i32)?;
+    }
+
+    // without the cap the kernel rejects explicit modifiers, and an invalid one means GBM
+    // allocated with an implicit layout the driver already knows about
+    let modifier = bo.modifier()?;
+    if modifier != Modifier::Invalid && gbm.get_driver_capability(DriverCapability::AddFB2Modifiers).unwrap_or(0) != 0 {
+        planes.modifier = Some(modifier);
+    }
+    let flags = if planes.modifier.is_some() { FbCmd2Flags::MODIFIERS } else { FbCmd2Flags::empty() };
+
+    // duplicated first, a framebuffer created before a failing dup would be left behind
+    let card = gbm.try_clone().map_err(Error::DupFd)?;
+    let handle = gbm.add_planar_framebuffer(&planes, flags).map_err(Error::Drm)?;
+    let fb = DrmFb { card, handle };
+    CREATED.fetch_add(1, Ordering::SeqCst);
+    ALIVE.fetch_add(1, Ordering::SeqCst);
+
+    // the buffer comes from our surface, so the device is still around
+    let _ = bo.set_userdata(fb);
+
+    Ok(handle)
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Stats {
+    /// Framebuffers created since start, one per buffer object the surface handed out.
+    pub created: usize,
+    /// Framebuffers whose buffer object hasn't been destroyed yet.
+    pub alive: usize,
+}
+
+pub fn stats() -> Stats {
+    Stats {
+        created: CREATED.load(Ordering::SeqCst),
+        alive: ALIVE.load(Ordering::SeqCst),
+    }
+}
+
+/// Prints the framebuffer counts, `when` tells the lines of a run apart. Buffer objects aren't
+/// counted on their own, each framebuffer lives exactly as long as the one it wraps.
+pub fn print_soak_report(when: &str, frames: u32) {
+    let stats = stats();
+    println!("soak ({}): {} frames, {} framebuffers created, {} alive",
+        when, frames, stats.created, stats.alive);
+}
@@ -0,0 +1,76 @@
+use sys::Card;
+
+use std::time::Instant;
+
+use drm::control::{Device as ControlDevice, PageFlipFlags};
+use gbm::Device;
+
+use crate::drm_common::{self, Drm};
+use crate::drm_fb::{self, DrmFb};
+use crate::egl::Egl;
+use crate::error::{Error, Result};
+use crate::perf::Perf;
+
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    gbm: &Device<Card>,
+    drm: &Drm,
+    gbm_surface: &gbm::Surface<DrmFb>,
+    egl: &Egl,
+    stop: &mut dyn FnMut(u32) -> bool,
+    perf: &mut Perf,
+    draw: &mut dyn FnMut(u32),
+    after_draw: &mut dyn FnMut(u32)
+) -> Result<u32> {
+    unsafe { sys::gles2::ClearColor(0., 0.5, 0.5, 1.0) };
+    unsafe { sys::gles2::Clear(sys::gles2::COLOR_BUFFER_BIT) };
+
+    let mut i = 0;
+
+    egl.swap_buffers()?;
+
+    let mut bo = unsafe { gbm_surface.lock_front_buffer() }.map_err(|_| Error::LockFrontBuffer)?;
+    let fb = drm_fb::get_from_bo(gbm, &mut bo)?;
+
+    gbm.set_crtc(
+        drm.crtc.handle(),
+        Some(fb),
+        (0, 0),
+        &[drm.connector.handle()],
+        Some(drm.mode)
+    ).map_err(Error::Drm)?;
+
+    while !stop(i) {
+        let draw_start = Instant::now();
+        draw(i);
+        let draw_end = Instant::now();
+        // before the swap, which hands the back buffer over, but not timed as drawing
+        after_draw(i);
+        i += 1;
+
+        let swap_start = Instant::now();
+        egl.swap_buffers()?;
+        let swap_end = Instant::now();
+        let mut next_bo = unsafe { gbm_surface.lock_front_buffer() }.map_err(|_| Error::LockFrontBuffer)?;
+        let fb = drm_fb::get_from_bo(gbm, &mut next_bo)?;
+
+        // * Here you could also update drm plane layers if you want
+        // * hw composition
+
+        gbm.page_flip(
+            drm.crtc.handle(),
+            fb,
+            PageFlipFlags::EVENT,
+            None,
+        ).map_err(Error::Drm)?;
+
+        let flip = drm_common::wait_for_flip(gbm)?;
+        perf.record(draw_end - draw_start, swap_end - swap_start, Some(flip));
+
+        bo = next_bo;
+    }
+
+    drop(bo);
+
+    Ok(i)
+}
@@ -1,9 +1,12 @@
-use gbm::{Device, Format};
+use gbm::{AsRaw, Device, Format};
 
 use sys::*;
 use std::ffi::{CStr, CString};
 
-pub fn query_string(display: egl::types::EGLDisplay, name: egl::types::EGLenum) -> String {
+use crate::drm_fb::DrmFb;
+use crate::error::{Error, Result};
+
+fn query_string(display: egl::types::EGLDisplay, name: egl::types::EGLenum) -> String {
     unsafe {
         CStr::from_ptr(egl::QueryString(display, name as i32)).to_string_lossy().into_owned()
     }
@@ -11,77 +14,130 @@ pub fn query_string(display: egl::types::EGLDisplay, name: egl::types::EGLenum)
 
 pub fn get_string(name: gles2::types::GLenum) -> String {
     unsafe {
-        CStr::from_ptr(gles2::GetString(name)).to_string_lossy().into_owned()
+        CStr::from_ptr(gles2::GetString(name) as *const _).to_string_lossy().into_owned()
     }
 }
 
-pub fn create_program(vs_src: &str, fs_src: &str) -> i32 {
-    let mut ret = 0;
+/// Looks up a GL or EGL extension entry point, `None` if the driver doesn't have it.
+pub fn proc_address(name: &str) -> Option<*const std::os::raw::c_void> {
+    let s = CString::new(name).unwrap();
+    let address = unsafe { egl::GetProcAddress(s.as_ptr()) };
 
-    let vertex_shader = unsafe { gles2::CreateShader(gles2::VERTEX_SHADER) };
+    if address.is_null() { None } else { Some(address as *const _) }
+}
 
-    unsafe {
-        let s = CString::new(vs_src.as_bytes()).unwrap();
-        gles2::ShaderSource(vertex_shader, 1, &s.as_ptr(), std::ptr::null())
-    };
-    unsafe { gles2::CompileShader(vertex_shader) };
-
-    unsafe { gles2::GetShaderiv(vertex_shader, gles2::COMPILE_STATUS, &mut ret) };
-    if ret == 0 {
-        dbg!("vertex shader compilation failed!");
-        unsafe { gles2::GetShaderiv(vertex_shader, gles2::INFO_LOG_LENGTH, &mut ret) };
-        if ret > 1 {
-            let mut log = vec![0_u8; ret as usize];
-            unsafe{ gles2::GetShaderInfoLog(vertex_shader, ret, std::ptr::null_mut(), log.as_mut_ptr()) };
-            unsafe{ dbg!(CStr::from_ptr(log.as_ptr())) };
-        }
+/// Display, context and, unless surfaceless, the window surface. Dropping it releases all of
+/// them and terminates the display.
+pub struct Egl {
+    display: egl::types::EGLDisplay,
+    config: egl::types::EGLConfig,
+    context: egl::types::EGLContext,
+    surface: egl::types::EGLSurface,
+    /// Samples of the window surface, 0 unless it is multisampled.
+    samples: u32,
+    /// Whether the window surface has a depth buffer.
+    depth: bool,
+    query_modifiers: Option<QueryDmaBufModifiersEXT>,
+}
 
-        return -1;
+impl Egl {
+    fn new(display: egl::types::EGLDisplay) -> Self {
+        Egl {
+            display,
+            config: std::ptr::null(),
+            context: egl::NO_CONTEXT,
+            surface: egl::NO_SURFACE,
+            samples: 0,
+            depth: false,
+            query_modifiers: None,
+        }
     }
 
-    let fragment_shader = unsafe { gles2::CreateShader(gles2::FRAGMENT_SHADER) };
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
 
-    unsafe {
-        let s = CString::new(fs_src.as_bytes()).unwrap();
-        gles2::ShaderSource(fragment_shader, 1, &s.as_ptr(), std::ptr::null())
-    };
-    unsafe { gles2::CompileShader(fragment_shader) };
+    /// Whether the window surface has a depth buffer, renderers have to draw into a
+    /// framebuffer object with one otherwise.
+    pub fn has_depth(&self) -> bool {
+        self.depth
+    }
 
-    unsafe { gles2::GetShaderiv(fragment_shader, gles2::COMPILE_STATUS, &mut ret) };
-    if ret == 0 {
-        // char *log;
+    /// Modifiers the driver can render `format` with, `None` if it doesn't support
+    /// `EGL_EXT_image_dma_buf_import_modifiers` and so can't tell.
+    pub fn render_modifiers(&self, format: Format) -> Option<Vec<u64>> {
+        let query = self.query_modifiers?;
+        let format = format as egl::EGLint;
 
-        dbg!("fragment shader compilation failed!");
-        unsafe { gles2::GetShaderiv(fragment_shader, gles2::INFO_LOG_LENGTH, &mut ret) };
+        let mut count = 0;
+        if unsafe { query(self.display, format, 0, std::ptr::null_mut(), std::ptr::null_mut(), &mut count) } != egl::TRUE {
+            return None;
+        }
 
-        if ret > 1 {
-            let mut log = vec![0_u8; ret as usize];
-            unsafe{ gles2::GetShaderInfoLog(fragment_shader, ret, std::ptr::null_mut(), log.as_mut_ptr()) };
-            unsafe{ dbg!(CStr::from_ptr(log.as_ptr())) };
+        let mut modifiers = vec![0_u64; count as usize];
+        let mut external_only = vec![egl::FALSE; count as usize];
+        if unsafe {
+            query(self.display, format, count, modifiers.as_mut_ptr(), external_only.as_mut_ptr(), &mut count)
+        } != egl::TRUE {
+            return None;
         }
 
-        return -1;
+        // external only modifiers can be sampled from but not rendered to
+        Some(modifiers.into_iter()
+            .zip(external_only)
+            .take(count as usize)
+            .filter(|&(_, external_only)| external_only == egl::FALSE)
+            .map(|(modifier, _)| modifier)
+            .collect())
     }
 
-    let program = unsafe { gles2::CreateProgram() };
+    /// Creates the window surface on `gbm_surface` and makes the context current on it.
+    ///
+    /// The `Egl` has to be dropped before `gbm_surface`.
+    pub fn create_window_surface(&mut self, gbm_surface: &gbm::Surface<DrmFb>) -> Result<()> {
+        self.surface = unsafe {
+            egl::CreateWindowSurface(self.display, self.config, gbm_surface.as_raw() as *const _, std::ptr::null())
+        };
+        if self.surface == egl::NO_SURFACE {
+            return Err(Error::egl("eglCreateWindowSurface"));
+        }
 
-    unsafe { gles2::AttachShader(program, vertex_shader) };
-    unsafe { gles2::AttachShader(program, fragment_shader) };
+        if unsafe { egl::MakeCurrent(self.display, self.surface, self.surface, self.context) } != egl::TRUE {
+            return Err(Error::egl("eglMakeCurrent"));
+        }
 
-    program as i32
-}
+        print_gl_info();
 
-pub fn init(
-    gbm: &Device<Card>,
-    samples: u32,
-    gbm_surface: &gbm::Surface<Card>,
-    pixel_format: Format
-) -> (egl::types::EGLDisplay, egl::types::EGLSurface) {
-    use gbm::AsRaw;
+        Ok(())
+    }
 
-    let mut major: egl::EGLint = -1;
-    let mut minor: egl::EGLint = -1;
+    pub fn swap_buffers(&self) -> Result<()> {
+        if unsafe { egl::SwapBuffers(self.display, self.surface) } != egl::TRUE {
+            return Err(Error::egl("eglSwapBuffers"));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Egl {
+    fn drop(&mut self) {
+        unsafe {
+            egl::MakeCurrent(self.display, egl::NO_SURFACE, egl::NO_SURFACE, egl::NO_CONTEXT);
+            if self.surface != egl::NO_SURFACE {
+                egl::DestroySurface(self.display, self.surface);
+            }
+            if self.context != egl::NO_CONTEXT {
+                egl::DestroyContext(self.display, self.context);
+            }
+            egl::Terminate(self.display);
+        }
+    }
+}
 
+/// Creates the display and a context for `pixel_format` on the GBM device, the window
+/// surface follows with `Egl::create_window_surface` once the GBM surface exists.
+pub fn init(gbm: &Device<Card>, samples: u32, pixel_format: Format) -> Result<Egl> {
     let egl_exts_client = query_string(egl::NO_DISPLAY, egl::EXTENSIONS);
     let ext_platform_base_address = if egl_exts_client.contains("EGL_EXT_platform_base") { unsafe {
         let s = CString::new("eglGetPlatformDisplayEXT").unwrap();
@@ -94,12 +150,191 @@ pub fn init(
     } else {
         unsafe { egl::GetDisplay(gbm.as_raw_mut() as *const _) }
     };
+    if display == egl::NO_DISPLAY {
+        return Err(Error::egl("eglGetDisplay"));
+    }
 
-    assert_eq!(unsafe { egl::Initialize(display, &mut major, &mut minor) }, 1);
+    let egl_exts_dpy = initialize(display, &egl_exts_client)?;
+    let mut state = Egl::new(display);
+
+    if egl_exts_dpy.contains("EGL_EXT_image_dma_buf_import_modifiers") {
+        state.query_modifiers = unsafe {
+            let s = CString::new("eglQueryDmaBufModifiersEXT").unwrap();
+            let address = egl::GetProcAddress(s.as_ptr());
+            if address.is_null() {
+                None
+            } else {
+                let query: QueryDmaBufModifiersEXT = std::mem::transmute(address);
+                Some(query)
+            }
+        };
+    }
 
-    let egl_exts_dpy = query_string(display, egl::EXTENSIONS);
+    let ([red, green, blue, alpha], float) = channel_sizes(pixel_format);
+    // fixed point is the default component type, float configs are only returned on request
+    if float && !egl_exts_dpy.contains("EGL_EXT_pixel_format_float") {
+        return Err(Error::MissingExtension("EGL_EXT_pixel_format_float"));
+    }
+
+    let attrib = |config, name: egl::types::EGLenum| unsafe {
+        let mut value = -1;
+        egl::GetConfigAttrib(display, config, name as i32, &mut value);
+        value
+    };
+    // eglChooseConfig also returns deeper configs, the surface needs the exact depth
+    let same_sizes = |config| {
+        attrib(config, egl::RED_SIZE) == red && attrib(config, egl::GREEN_SIZE) == green &&
+            attrib(config, egl::BLUE_SIZE) == blue && attrib(config, egl::ALPHA_SIZE) == alpha
+    };
+
+    let choose = |samples: u32, depth_size: egl::EGLint| {
+        let mut config_attribs = vec![
+            egl::SURFACE_TYPE as i32, egl::WINDOW_BIT as i32,
+            egl::RED_SIZE as i32, red,
+            egl::GREEN_SIZE as i32, green,
+            egl::BLUE_SIZE as i32, blue,
+            egl::ALPHA_SIZE as i32, alpha,
+            egl::RENDERABLE_TYPE as i32, egl::OPENGL_ES2_BIT as i32,
+            egl::SAMPLE_BUFFERS as i32, (samples > 0) as i32,
+            egl::SAMPLES as i32, samples as i32,
+            egl::DEPTH_SIZE as i32, depth_size,
+        ];
+        if float {
+            config_attribs.extend_from_slice(&[COLOR_COMPONENT_TYPE_EXT, COLOR_COMPONENT_TYPE_FLOAT_EXT]);
+        }
+        config_attribs.push(egl::NONE as i32);
+
+        // prefer the config whose visual is the GBM format, some drivers leave the visual unset
+        choose_config(display, &config_attribs, |c| {
+            same_sizes(c) && attrib(c, egl::NATIVE_VISUAL_ID) == pixel_format as i32
+        }).or_else(|_| choose_config(display, &config_attribs, same_sizes))
+    };
+
+    // multisampled window surfaces are rare on GBM, the caller resolves from a multisampled
+    // framebuffer object instead when there is no such config
+    let egl_config = match choose(samples, DEPTH_SIZE) {
+        Err(Error::NoMatchingConfig) if samples > 0 => {
+            println!("no EGL config with {} samples, falling back to a multisampled framebuffer", samples);
+            choose(0, DEPTH_SIZE)
+        }
+        result => result,
+    };
+    // the caller adds a framebuffer object with its own depth buffer then
+    let egl_config = match egl_config {
+        Err(Error::NoMatchingConfig) => {
+            println!("no EGL config with a depth buffer, rendering into a framebuffer object with one");
+            choose(0, 0)?
+        }
+        result => result?,
+    };
+    state.samples = attrib(egl_config, egl::SAMPLES).max(0) as u32;
+    state.depth = attrib(egl_config, egl::DEPTH_SIZE) > 0;
+
+    state.config = egl_config;
+    state.context = create_context(display, egl_config)?;
+
+    Ok(state)
+}
+
+/// Creates a context on Mesa's surfaceless platform, rendering then has to go to a
+/// framebuffer object as there is no window surface, multisampled or not.
+pub fn init_surfaceless() -> Result<Egl> {
+    let egl_exts_client = query_string(egl::NO_DISPLAY, egl::EXTENSIONS);
+    for &ext in &["EGL_EXT_platform_base", "EGL_MESA_platform_surfaceless"] {
+        if !egl_exts_client.contains(ext) {
+            return Err(Error::MissingExtension(ext));
+        }
+    }
+
+    let get_platform_display: GetPlatformDisplayEXT = unsafe {
+        let s = CString::new("eglGetPlatformDisplayEXT").unwrap();
+        let address = egl::GetProcAddress(s.as_ptr());
+        if address.is_null() {
+            return Err(Error::MissingExtension("EGL_EXT_platform_base"));
+        }
+        std::mem::transmute(address)
+    };
 
-    let _modifiers_supported = egl_exts_dpy.contains("EGL_EXT_image_dma_buf_import_modifiers");
+    let display = unsafe {
+        get_platform_display(PLATFORM_SURFACELESS_MESA, std::ptr::null_mut(), std::ptr::null())
+    };
+    if display == egl::NO_DISPLAY {
+        return Err(Error::egl("eglGetPlatformDisplayEXT"));
+    }
+
+    let egl_exts_dpy = initialize(display, &egl_exts_client)?;
+    let mut state = Egl::new(display);
+    if !egl_exts_dpy.contains("EGL_KHR_surfaceless_context") {
+        return Err(Error::MissingExtension("EGL_KHR_surfaceless_context"));
+    }
+
+    let config_attribs = [
+        egl::SURFACE_TYPE as i32, egl::PBUFFER_BIT as i32,
+        egl::RED_SIZE as i32, 1,
+        egl::GREEN_SIZE as i32, 1,
+        egl::BLUE_SIZE as i32, 1,
+        egl::ALPHA_SIZE as i32, 0,
+        egl::RENDERABLE_TYPE as i32, egl::OPENGL_ES2_BIT as i32,
+        egl::NONE as i32
+    ];
+
+    let egl_config = choose_config(display, &config_attribs, |_| true)?;
+    state.config = egl_config;
+    state.context = create_context(display, egl_config)?;
+
+    if unsafe { egl::MakeCurrent(display, egl::NO_SURFACE, egl::NO_SURFACE, state.context) } != egl::TRUE {
+        return Err(Error::egl("eglMakeCurrent"));
+    }
+
+    print_gl_info();
+
+    Ok(state)
+}
+
+/// Bits of the red, green, blue and alpha channels of `format`, and whether they are half
+/// floats.
+fn channel_sizes(format: Format) -> ([egl::EGLint; 4], bool) {
+    match format {
+        Format::Rgb565 | Format::Bgr565 => ([5, 6, 5, 0], false),
+        Format::Xrgb2101010 | Format::Xbgr2101010 => ([10, 10, 10, 0], false),
+        Format::Argb2101010 | Format::Abgr2101010 => ([10, 10, 10, 2], false),
+        Format::Argb8888 | Format::Abgr8888 | Format::Rgba8888 => ([8, 8, 8, 8], false),
+        Format::Xbgr16161616f => ([16, 16, 16, 0], true),
+        _ => ([8, 8, 8, 0], false),
+    }
+}
+
+type GetPlatformDisplayEXT = unsafe extern "system" fn(
+    egl::types::EGLenum,
+    *mut std::os::raw::c_void,
+    *const egl::EGLint
+) -> egl::types::EGLDisplay;
+
+type QueryDmaBufModifiersEXT = unsafe extern "system" fn(
+    egl::types::EGLDisplay,
+    egl::EGLint,
+    egl::EGLint,
+    *mut u64,
+    *mut egl::types::EGLBoolean,
+    *mut egl::EGLint
+) -> egl::types::EGLBoolean;
+
+const PLATFORM_SURFACELESS_MESA: egl::types::EGLenum = 0x31DD;
+/// Minimum depth buffer size requested from the window surface config.
+const DEPTH_SIZE: egl::EGLint = 16;
+const COLOR_COMPONENT_TYPE_EXT: egl::EGLint = 0x3339;
+const COLOR_COMPONENT_TYPE_FLOAT_EXT: egl::EGLint = 0x333B;
+
+/// Initializes the display, binds the GLES API and returns the display extensions.
+fn initialize(display: egl::types::EGLDisplay, egl_exts_client: &str) -> Result<String> {
+    let mut major: egl::EGLint = -1;
+    let mut minor: egl::EGLint = -1;
+
+    if unsafe { egl::Initialize(display, &mut major, &mut minor) } != egl::TRUE {
+        return Err(Error::egl("eglInitialize"));
+    }
+
+    let egl_exts_dpy = query_string(display, egl::EXTENSIONS);
 
     println!("Using display {:?} with EGL version {:?}.{:?}", display, major, minor);
 
@@ -111,35 +346,28 @@ pub fn init(
     println!("  display extensions: \"{}\"", egl_exts_dpy);
     println!("===================================");
 
-    assert_eq!(unsafe {egl::BindAPI(egl::OPENGL_ES_API)}, 1);
+    if unsafe { egl::BindAPI(egl::OPENGL_ES_API) } != egl::TRUE {
+        return Err(Error::egl("eglBindAPI"));
+    }
+
+    Ok(egl_exts_dpy)
+}
 
+fn choose_config<F>(
+    display: egl::types::EGLDisplay,
+    config_attribs: &[egl::EGLint],
+    filter: F
+) -> Result<egl::types::EGLConfig> where F: Fn(egl::types::EGLConfig) -> bool {
     let mut config_size = -1;
     let mut matched_config_size = -1;
 
-    let config_attribs = vec![
-        egl::SURFACE_TYPE as i32, egl::WINDOW_BIT as i32,
-        egl::RED_SIZE as i32, 1,
-        egl::GREEN_SIZE as i32, 1,
-        egl::BLUE_SIZE as i32, 1,
-        egl::ALPHA_SIZE as i32, 0,
-        egl::RENDERABLE_TYPE as i32, egl::OPENGL_ES2_BIT as i32,
-        egl::SAMPLES as i32, samples as i32,
-        egl::NONE as i32
-    ];
-
-    let context_attribs = vec![
-        egl::CONTEXT_CLIENT_VERSION as i32, 2,
-        egl::NONE as i32
-    ];
-
-    assert_eq!(unsafe {
-        egl::GetConfigs(display, std::ptr::null_mut(), 0, &mut config_size)
-    }, 1);
+    if unsafe { egl::GetConfigs(display, std::ptr::null_mut(), 0, &mut config_size) } != egl::TRUE {
+        return Err(Error::egl("eglGetConfigs"));
+    }
 
     let mut configs = vec![std::ptr::null() as egl::types::EGLConfig; config_size as usize];
-    dbg!(config_size);
 
-    assert_eq!(unsafe {
+    if unsafe {
         egl::ChooseConfig(
             display,
             config_attribs.as_ptr(),
@@ -147,27 +375,37 @@ pub fn init(
             config_size,
             &mut matched_config_size
         )
-    }, 1);
+    } != egl::TRUE {
+        return Err(Error::egl("eglChooseConfig"));
+    }
 
-    let egl_config = configs.iter().find(|&&c| unsafe {
-        let mut value = -1;
-        egl::GetConfigAttrib(display, c, egl::NATIVE_VISUAL_ID as i32, &mut value);
-        value
-    } == pixel_format.as_ffi() as i32).unwrap();
+    configs.iter()
+        .take(matched_config_size as usize)
+        .find(|&&c| filter(c))
+        .cloned()
+        .ok_or(Error::NoMatchingConfig)
+}
 
-    let egl_context = unsafe {
-        egl::CreateContext(display, *egl_config, egl::NO_CONTEXT, context_attribs.as_ptr())
-    };
-    assert!(egl_context != std::ptr::null());
+fn create_context(
+    display: egl::types::EGLDisplay,
+    config: egl::types::EGLConfig
+) -> Result<egl::types::EGLContext> {
+    let context_attribs = [
+        egl::CONTEXT_CLIENT_VERSION as i32, 2,
+        egl::NONE as i32
+    ];
 
-    let egl_surface = unsafe {
-        egl::CreateWindowSurface(display, *egl_config, gbm_surface.as_raw() as *const _, std::ptr::null())
+    let egl_context = unsafe {
+        egl::CreateContext(display, config, egl::NO_CONTEXT, context_attribs.as_ptr())
     };
+    if egl_context == egl::NO_CONTEXT {
+        return Err(Error::egl("eglCreateContext"));
+    }
 
-    assert!(egl_surface != egl::NO_SURFACE);
-
-    unsafe { egl::MakeCurrent(display, egl_surface, egl_surface, egl_context) };
+    Ok(egl_context)
+}
 
+fn print_gl_info() {
     println!("OpenGL ES 2.x information:");
     println!("  version: \"{}\"", get_string(gles2::VERSION));
     println!("  shading language version: \"{}\"", get_string(gles2::SHADING_LANGUAGE_VERSION));
@@ -175,6 +413,4 @@ pub fn init(
     println!("  renderer: \"{}\"", get_string(gles2::RENDERER));
     println!("  extensions: \"{}\"", get_string(gles2::EXTENSIONS));
     println!("===================================");
-
-    (display, egl_surface)
 }
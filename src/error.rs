@@ -0,0 +1,116 @@
+use std::fmt;
+use std::io;
+
+use sys::egl;
+
+use crate::config::{ConnectorSelector, VideoMode};
+
+#[derive(Debug)]
+pub enum Error {
+    /// The DRM device node couldn't be opened.
+    DeviceOpen { path: String, source: io::Error },
+    /// Creating the GBM device or surface failed.
+    Gbm(io::Error),
+    /// The GBM surface had no buffer to hand out after a swap.
+    LockFrontBuffer,
+    /// A drm-rs call failed.
+    Drm(io::Error),
+    /// Duplicating the DRM device's file descriptor failed.
+    DupFd(io::Error),
+    NoConnector,
+    ConnectorNotFound(ConnectorSelector),
+    ConnectorDisconnected(String),
+    /// The connector reports no modes at all.
+    NoModes(String),
+    NoMode(VideoMode),
+    NoEncoder,
+    NoCrtc,
+    /// An EGL call failed, `code` is what `eglGetError` returned afterwards.
+    Egl { call: &'static str, code: egl::EGLint },
+    MissingExtension(&'static str),
+    NoMatchingConfig,
+    ShaderCompile { stage: &'static str, log: String },
+    ProgramLink { log: String },
+    /// A shader file given on the command line couldn't be read.
+    ShaderFile { path: String, source: io::Error },
+    /// Setting up the inotify watch on the shader files failed.
+    Inotify(io::Error),
+    IncompleteFramebuffer(u32),
+    UnknownRenderer(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Builds an `Egl` error from the last EGL error of the calling thread.
+    pub fn egl(call: &'static str) -> Self {
+        Error::Egl { call, code: unsafe { egl::GetError() } }
+    }
+}
+
+fn egl_error_name(code: egl::EGLint) -> &'static str {
+    match code as egl::types::EGLenum {
+        egl::SUCCESS => "EGL_SUCCESS",
+        egl::NOT_INITIALIZED => "EGL_NOT_INITIALIZED",
+        egl::BAD_ACCESS => "EGL_BAD_ACCESS",
+        egl::BAD_ALLOC => "EGL_BAD_ALLOC",
+        egl::BAD_ATTRIBUTE => "EGL_BAD_ATTRIBUTE",
+        egl::BAD_CONFIG => "EGL_BAD_CONFIG",
+        egl::BAD_CONTEXT => "EGL_BAD_CONTEXT",
+        egl::BAD_CURRENT_SURFACE => "EGL_BAD_CURRENT_SURFACE",
+        egl::BAD_DISPLAY => "EGL_BAD_DISPLAY",
+        egl::BAD_MATCH => "EGL_BAD_MATCH",
+        egl::BAD_NATIVE_PIXMAP => "EGL_BAD_NATIVE_PIXMAP",
+        egl::BAD_NATIVE_WINDOW => "EGL_BAD_NATIVE_WINDOW",
+        egl::BAD_PARAMETER => "EGL_BAD_PARAMETER",
+        egl::BAD_SURFACE => "EGL_BAD_SURFACE",
+        egl::CONTEXT_LOST => "EGL_CONTEXT_LOST",
+        _ => "unknown EGL error",
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::DeviceOpen { path, source } => write!(f, "failed to open {}: {}", path, source),
+            Error::Gbm(e) => write!(f, "GBM error: {}", e),
+            Error::LockFrontBuffer => write!(f, "failed to lock the GBM front buffer"),
+            Error::Drm(e) => write!(f, "DRM error: {}", e),
+            Error::DupFd(e) => write!(f, "failed to duplicate the DRM file descriptor: {}", e),
+            Error::NoConnector => write!(f, "no connected connector found"),
+            Error::ConnectorNotFound(connector) => write!(f, "connector {} not found", connector),
+            Error::ConnectorDisconnected(name) => write!(f, "connector {} is not connected", name),
+            Error::NoModes(name) => write!(f, "connector {} has no modes", name),
+            Error::NoMode(mode) => write!(f, "requested mode not found: {}", mode),
+            Error::NoEncoder => write!(f, "the connector has no encoders"),
+            Error::NoCrtc => write!(f, "no free CRTC found for the connector"),
+            Error::Egl { call, code } =>
+                write!(f, "{} failed: {} (0x{:x})", call, egl_error_name(*code), code),
+            Error::MissingExtension(ext) => write!(f, "{} is not supported", ext),
+            Error::NoMatchingConfig => write!(f, "no EGL config matches the requested attributes"),
+            Error::ShaderCompile { stage, log } => write!(f, "{} shader compilation failed:\n{}", stage, log),
+            Error::ProgramLink { log } => write!(f, "program linking failed:\n{}", log),
+            Error::ShaderFile { path, source } => write!(f, "failed to read {}: {}", path, source),
+            Error::Inotify(e) => write!(f, "failed to watch the shader files: {}", e),
+            Error::IncompleteFramebuffer(status) =>
+                write!(f, "framebuffer is incomplete: 0x{:x}", status),
+            Error::UnknownRenderer(name) => write!(f, "no renderer named {}", name),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::DeviceOpen { source, .. } | Error::ShaderFile { source, .. } => Some(source),
+            Error::Gbm(e) | Error::Drm(e) | Error::DupFd(e) | Error::Inotify(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<gbm::DeviceDestroyedError> for Error {
+    fn from(e: gbm::DeviceDestroyedError) -> Self {
+        Error::Gbm(io::Error::new(io::ErrorKind::Other, e))
+    }
+}
@@ -1,4 +1,9 @@
 use std::f32::consts::PI;
+use std::ops::Mul;
+
+#[cfg(feature = "simd")]
+use crate::simd;
+use crate::vector::Vec3;
 
 #[derive(Debug, Copy, Clone)]
 pub struct ESMatrix([[f32; 4]; 4]);
@@ -10,6 +15,11 @@ impl Default for ESMatrix {
 }
 
 impl ESMatrix {
+    /// Builds a matrix from the layout `m` returns.
+    pub fn new(m: [[f32; 4]; 4]) -> ESMatrix {
+        ESMatrix(m)
+    }
+
     pub fn m(self) -> [[f32; 4]; 4] {
         self.0
     }
@@ -43,6 +53,8 @@ impl ESMatrix {
         result
     }
 
+    /// Rotates by `angle` degrees around the axis. Like `esRotate` it turns clockwise when
+    /// looking down the axis towards the origin, the opposite of `glRotate` and `Quat`.
     pub fn rotate(self, angle: f32, x: f32, y: f32, z: f32) -> ESMatrix {
         let mag = (x * x + y * y + z * z).sqrt();
 
@@ -92,7 +104,21 @@ impl ESMatrix {
         ESMatrix::multiply(rot_mat, self)
     }
 
+    /// The stored product `src_a src_b`, which applies `src_a` first. Uses the `simd`
+    /// kernels when the feature is enabled.
     pub fn multiply(src_a: ESMatrix, src_b: ESMatrix) -> ESMatrix {
+        #[cfg(feature = "simd")]
+        {
+            ESMatrix(simd::multiply(&src_a.0, &src_b.0))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            ESMatrix::multiply_scalar(src_a, src_b)
+        }
+    }
+
+    /// `multiply` without the `simd` kernels, kept around to compare against.
+    pub fn multiply_scalar(src_a: ESMatrix, src_b: ESMatrix) -> ESMatrix {
         let mut result = ESMatrix::default();
 
         (0_usize..4_usize).for_each(|i| {
@@ -157,52 +183,162 @@ impl ESMatrix {
         ESMatrix::multiply(frust, self)
     }
 
-// pub fn es_scale(ESMatrix *result, GLfloat sx, GLfloat sy, GLfloat sz) {
-//     result->m[0][0] *= sx;
-//     result->m[0][1] *= sx;
-//     result->m[0][2] *= sx;
-//     result->m[0][3] *= sx;
+    pub fn scale(self, sx: f32, sy: f32, sz: f32) -> ESMatrix {
+        let mut result = self;
+        result.0[0].iter_mut().for_each(|v| *v *= sx);
+        result.0[1].iter_mut().for_each(|v| *v *= sy);
+        result.0[2].iter_mut().for_each(|v| *v *= sz);
+
+        result
+    }
+
+    /// Symmetric perspective projection, `fovy` is the vertical field of view in degrees and
+    /// `aspect` the width over the height of the viewport.
+    pub fn perspective(self, fovy: f32, aspect: f32, near_z: f32, far_z: f32) -> ESMatrix {
+        let frustum_h = (fovy / 360.0 * PI).tan() * near_z;
+        let frustum_w = frustum_h * aspect;
+
+        self.frustum(-frustum_w, frustum_w, -frustum_h, frustum_h, near_z, far_z)
+    }
+
+    pub fn ortho(self, left: f32, right: f32, bottom: f32, top: f32, near_z: f32, far_z: f32) -> ESMatrix {
+        let delta_x = right - left;
+        let delta_y = top - bottom;
+        let delta_z = far_z - near_z;
+
+        if delta_x == 0.0 || delta_y == 0.0 || delta_z == 0.0 {
+            return self;
+        }
+
+        let mut ortho = ESMatrix::identity();
+        ortho.0[0][0] = 2.0 / delta_x;
+        ortho.0[3][0] = -(right + left) / delta_x;
+        ortho.0[1][1] = 2.0 / delta_y;
+        ortho.0[3][1] = -(top + bottom) / delta_y;
+        ortho.0[2][2] = -2.0 / delta_z;
+        ortho.0[3][2] = -(near_z + far_z) / delta_z;
+
+        ESMatrix::multiply(ortho, self)
+    }
+
+    /// Camera at `eye` looking at `center`, with `up` pointing towards the top of the view.
+    ///
+    /// Leaves the matrix unchanged if `eye` and `center` coincide or `up` is parallel to the
+    /// viewing direction.
+    pub fn look_at(self, eye: [f32; 3], center: [f32; 3], up: [f32; 3]) -> ESMatrix {
+        let (eye, center, up) = (Vec3::from(eye), Vec3::from(center), Vec3::from(up));
+        let forward = match (center - eye).normalize() {
+            Some(forward) => forward,
+            None => return self,
+        };
+        let right = match forward.cross(up).normalize() {
+            Some(right) => right,
+            None => return self,
+        };
+        let up = right.cross(forward);
+
+        let mut view = ESMatrix::identity();
+        view.0[0][..3].copy_from_slice(&[right.x, up.x, -forward.x]);
+        view.0[1][..3].copy_from_slice(&[right.y, up.y, -forward.y]);
+        view.0[2][..3].copy_from_slice(&[right.z, up.z, -forward.z]);
+        view.0[3][0] = -right.dot(eye);
+        view.0[3][1] = -up.dot(eye);
+        view.0[3][2] = forward.dot(eye);
+
+        ESMatrix::multiply(view, self)
+    }
 
-//     result->m[1][0] *= sy;
-//     result->m[1][1] *= sy;
-//     result->m[1][2] *= sy;
-//     result->m[1][3] *= sy;
+    pub fn transpose(self) -> ESMatrix {
+        let mut result = ESMatrix::default();
+        for i in 0..4 {
+            for j in 0..4 {
+                result.0[i][j] = self.0[j][i];
+            }
+        }
 
-//     result->m[2][0] *= sz;
-//     result->m[2][1] *= sz;
-//     result->m[2][2] *= sz;
-//     result->m[2][3] *= sz;
-// }
+        result
+    }
 
+    pub fn determinant(self) -> f32 {
+        let m = self.0;
+        (0..4).map(|j| m[0][j] * cofactor(&m, 0, j)).sum()
+    }
 
-// esPerspective(ESMatrix *result, float fovy, float aspect, float nearZ, float farZ)
-// {
-//    GLfloat frustumW, frustumH;
+    /// Returns `None` if the matrix is singular.
+    pub fn inverse(self) -> Option<ESMatrix> {
+        let det = self.determinant();
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
 
-//    frustumH = tanf( fovy / 360.0f * PI ) * nearZ;
-//    frustumW = frustumH * aspect;
+        // the inverse is the transposed cofactor matrix over the determinant
+        let mut result = ESMatrix::default();
+        for i in 0..4 {
+            for j in 0..4 {
+                result.0[j][i] = cofactor(&self.0, i, j) / det;
+            }
+        }
 
-//    esFrustum( result, -frustumW, frustumW, -frustumH, frustumH, nearZ, farZ );
-// }
+        Some(result)
+    }
 
-// esOrtho(ESMatrix *result, float left, float right, float bottom, float top, float nearZ, float farZ)
-// {
-//     float       deltaX = right - left;
-//     float       deltaY = top - bottom;
-//     float       deltaZ = farZ - nearZ;
-//     ESMatrix    ortho;
+    /// Inverse transpose of the upper-left 3x3, laid out for `glUniformMatrix3fv` like `l`
+    /// is for the full matrix.
+    ///
+    /// Unlike the plain 3x3 it keeps normals perpendicular to surfaces under non-uniform
+    /// scaling. A singular 3x3 has no inverse, the plain one is returned then.
+    pub fn normal_matrix(self) -> [f32; 9] {
+        let m = self.0;
+        let upper = [
+            m[0][0], m[0][1], m[0][2],
+            m[1][0], m[1][1], m[1][2],
+            m[2][0], m[2][1], m[2][2],
+        ];
+
+        let minor = |i: usize, j: usize| {
+            let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+            let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+
+        // with the cyclic indices the minors already carry the cofactor signs
+        let det = m[0][0] * minor(0, 0) + m[0][1] * minor(0, 1) + m[0][2] * minor(0, 2);
+        if det == 0.0 || !det.is_finite() {
+            return upper;
+        }
+
+        // the inverse transpose is the cofactor matrix over the determinant
+        let mut result = [0.0; 9];
+        for i in 0..3 {
+            for j in 0..3 {
+                result[i * 3 + j] = minor(i, j) / det;
+            }
+        }
 
-//     if ( (deltaX == 0.0f) || (deltaY == 0.0f) || (deltaZ == 0.0f) )
-//         return;
+        result
+    }
+}
 
-//     esMatrixLoadIdentity(&ortho);
-//     ortho.m[0][0] = 2.0f / deltaX;
-//     ortho.m[3][0] = -(right + left) / deltaX;
-//     ortho.m[1][1] = 2.0f / deltaY;
-//     ortho.m[3][1] = -(top + bottom) / deltaY;
-//     ortho.m[2][2] = -2.0f / deltaZ;
-//     ortho.m[3][2] = -(nearZ + farZ) / deltaZ;
+/// Signed minor of the element at row `row`, column `col`.
+fn cofactor(m: &[[f32; 4]; 4], row: usize, col: usize) -> f32 {
+    // the three indices left once `skip` is taken out, in order
+    let others = |skip: usize| [0, 1, 2].map(|k| k + (k >= skip) as usize);
+    let (rows, cols) = (others(row), others(col));
+    let at = |i: usize, j: usize| m[rows[i]][cols[j]];
 
-//     esMatrixMultiply(result, &ortho, result);
-// }
+    let minor =
+        at(0, 0) * (at(1, 1) * at(2, 2) - at(1, 2) * at(2, 1)) -
+        at(0, 1) * (at(1, 0) * at(2, 2) - at(1, 2) * at(2, 0)) +
+        at(0, 2) * (at(1, 0) * at(2, 1) - at(1, 1) * at(2, 0));
+
+    if (row + col) % 2 == 0 { minor } else { -minor }
+}
+
+/// Composes the transforms like the shaders do, `a * b` applies `b` first.
+impl Mul for ESMatrix {
+    type Output = ESMatrix;
+
+    fn mul(self, other: ESMatrix) -> ESMatrix {
+        ESMatrix::multiply(other, self)
+    }
 }
@@ -0,0 +1,81 @@
+use sys::gles2 as gl;
+
+use std::time::Instant;
+
+use crate::capture;
+use crate::config::DisplayConfig;
+use crate::egl;
+use crate::error::{Error, Result};
+use crate::msaa;
+use crate::offscreen::Offscreen;
+use crate::perf::Perf;
+use crate::renderer::{Renderer, TeardownGuard};
+
+const DEFAULT_SIZE: (u32, u32) = (640, 480);
+
+/// Runs the frame loop on the surfaceless platform, rendering into an offscreen framebuffer
+/// without touching KMS, until `stop` returns true for the number of the next frame.
+///
+/// Only the size from `config.video_mode`, the samples, perf and capture settings apply.
+pub fn run(
+    config: &DisplayConfig,
+    renderer: &mut dyn Renderer,
+    stop: &mut dyn FnMut(u32) -> bool
+) -> Result<()> {
+    let (width, height) = match config.video_mode {
+        Some(ref video_mode) => parse_size(&video_mode.name).ok_or_else(|| Error::NoMode(video_mode.clone()))?,
+        None => DEFAULT_SIZE,
+    };
+
+    // declared first so the offscreen framebuffer is deleted while the context is current
+    let _egl = egl::init_surfaceless()?;
+    let offscreen = Offscreen::new(width, height)?;
+    let msaa = msaa::create(width, height, config.samples, true, gbm::Format::Abgr8888);
+
+    // declared after the context so it's torn down first, also if init fails halfway
+    let mut renderer = TeardownGuard::new(renderer);
+    // the offscreen texture is RGBA8 whatever the scanout format
+    renderer.init(width, height, gbm::Format::Abgr8888)?;
+
+    let mut perf = Perf::new(config);
+    let start = Instant::now();
+    let mut i = 0;
+
+    while !stop(i) {
+        let draw_start = Instant::now();
+        if let Some(ref msaa) = msaa {
+            msaa.bind();
+        }
+        renderer.draw(i, start.elapsed());
+        if let Some(ref msaa) = msaa {
+            msaa.resolve();
+        }
+        // there is no swap, waiting for the GPU takes its place
+        let finish_start = Instant::now();
+        unsafe { gl::Finish() };
+        perf.record(finish_start - draw_start, finish_start.elapsed(), None);
+
+        if let Some(ref capture) = config.capture {
+            if capture.wants(i) {
+                capture::save_frame(capture, i, width, height, gbm::Format::Abgr8888);
+            }
+        }
+
+        i += 1;
+    }
+
+    perf.finish();
+    drop(renderer);
+    println!("rendered {} frames offscreen at {}x{}", i, offscreen.size().0, offscreen.size().1);
+
+    Ok(())
+}
+
+/// Parses a `<width>x<height>` mode name.
+fn parse_size(name: &str) -> Option<(u32, u32)> {
+    let mut parts = name.splitn(2, 'x');
+    let width = parts.next()?.parse().ok()?;
+    let height = parts.next()?.parse().ok()?;
+
+    Some((width, height))
+}
@@ -0,0 +1,154 @@
+//! Shader sources read from files instead of the built in ones, watched with inotify so
+//! renderers can rebuild their program while running.
+
+use std::ffi::{CString, OsStr};
+use std::fs;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::RawFd;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+/// Events that mean a file has new contents: written in place, or replaced by a rename the
+/// way most editors save. Creation isn't one, a new file is still empty at that point and is
+/// reported again when closed after writing.
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE | libc::IN_MOVED_TO;
+
+/// Vertex and fragment shader files standing in for a renderer's built in sources, either
+/// can be left out.
+pub struct ShaderFiles {
+    vertex: Option<PathBuf>,
+    fragment: Option<PathBuf>,
+    inotify: Option<Inotify>,
+}
+
+impl ShaderFiles {
+    pub fn new(vertex: Option<PathBuf>, fragment: Option<PathBuf>) -> Self {
+        ShaderFiles { vertex, fragment, inotify: None }
+    }
+
+    /// Reads the vertex and fragment sources, falling back to `vertex` and `fragment` for
+    /// the files not given.
+    pub fn load(&self, vertex: &str, fragment: &str) -> Result<(String, String)> {
+        let read = |path: &Option<PathBuf>, builtin: &str| match path {
+            Some(path) => fs::read_to_string(path)
+                .map_err(|source| Error::ShaderFile { path: path.display().to_string(), source }),
+            None => Ok(builtin.to_owned()),
+        };
+
+        Ok((read(&self.vertex, vertex)?, read(&self.fragment, fragment)?))
+    }
+
+    /// Starts watching the files, `changed` reports nothing before.
+    pub fn watch(&mut self) -> Result<()> {
+        let mut inotify = Inotify::new().map_err(Error::Inotify)?;
+        for path in self.vertex.iter().chain(self.fragment.iter()) {
+            inotify.add(path).map_err(Error::Inotify)?;
+        }
+
+        self.inotify = Some(inotify);
+        Ok(())
+    }
+
+    /// Whether any of the files was written or replaced since the last call, without
+    /// blocking.
+    pub fn changed(&mut self) -> bool {
+        let inotify = match self.inotify {
+            Some(ref mut inotify) => inotify,
+            None => return false,
+        };
+
+        let files: Vec<&Path> = self.vertex.iter().chain(self.fragment.iter()).map(PathBuf::as_path).collect();
+        let mut changed = false;
+        // all pending events are drained, a save often comes as several
+        while let Some(paths) = inotify.read() {
+            changed |= paths.iter().any(|path| files.iter().any(|file| same_file(file, path)));
+        }
+
+        changed
+    }
+}
+
+/// Compares a path as given on the command line with one built from a watched directory.
+fn same_file(file: &Path, event: &Path) -> bool {
+    file.file_name() == event.file_name() && watch_dir(file) == event.parent().unwrap_or(Path::new(""))
+}
+
+/// The directory to watch for `file`, the files themselves are replaced on most saves.
+fn watch_dir(file: &Path) -> &Path {
+    match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// Non-blocking inotify instance watching directories.
+struct Inotify {
+    fd: RawFd,
+    /// Watch descriptors and the directories they were added for.
+    watches: Vec<(libc::c_int, PathBuf)>,
+}
+
+impl Inotify {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Inotify { fd, watches: Vec::new() })
+    }
+
+    /// Watches the directory `file` is in.
+    fn add(&mut self, file: &Path) -> io::Result<()> {
+        let dir = watch_dir(file);
+        let s = CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+        let wd = unsafe { libc::inotify_add_watch(self.fd, s.as_ptr(), WATCH_MASK) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // the same directory gives the same descriptor
+        if !self.watches.iter().any(|&(w, _)| w == wd) {
+            self.watches.push((wd, dir.to_owned()));
+        }
+        Ok(())
+    }
+
+    /// Paths of the next batch of events, `None` when there are no more.
+    fn read(&mut self) -> Option<Vec<PathBuf>> {
+        // room for plenty of events with names up to NAME_MAX
+        let mut buf = [0_u8; 4096 + 256];
+        let len = unsafe { libc::read(self.fd, buf.as_mut_ptr() as *mut _, buf.len()) };
+        if len <= 0 {
+            return None;
+        }
+
+        let header = std::mem::size_of::<libc::inotify_event>();
+        let mut paths = Vec::new();
+        let mut offset = 0;
+        while offset + header <= len as usize {
+            let event: libc::inotify_event = unsafe { std::ptr::read_unaligned(buf[offset..].as_ptr() as *const _) };
+            let name = &buf[offset + header..offset + header + event.len as usize];
+            // the name is padded with nul bytes
+            let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+
+            if let Some((_, dir)) = self.watches.iter().find(|&&(wd, _)| wd == event.wd) {
+                paths.push(dir.join(OsStr::from_bytes(name)));
+            }
+
+            offset += header + event.len as usize;
+        }
+
+        Some(paths)
+    }
+}
+
+impl Drop for Inotify {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
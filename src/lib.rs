@@ -0,0 +1,36 @@
+//! kmscube as a library: KMS output setup, EGL context creation, matrix math and the frame
+//! loop, with the demos implemented as renderers on top.
+
+extern crate drm;
+extern crate gbm;
+extern crate libc;
+extern crate sys;
+
+pub mod capture;
+pub mod config;
+pub mod cube_smooth;
+pub mod cube_tex;
+pub mod display;
+pub mod drm_atomic;
+pub mod drm_common;
+pub mod drm_fb;
+pub mod drm_legacy;
+pub mod egl;
+pub mod error;
+pub mod es_matrix;
+pub mod headless;
+pub mod hot_reload;
+pub mod msaa;
+pub mod offscreen;
+pub mod perf;
+pub mod quat;
+pub mod renderer;
+pub mod shader;
+pub mod shutdown;
+#[cfg(feature = "simd")]
+mod simd;
+pub mod vector;
+
+pub use crate::error::{Error, Result};
+pub use crate::config::DisplayConfig;
+pub use crate::renderer::Renderer;
@@ -0,0 +1,474 @@
+use std::os::raw::c_void;
+
+use sys::gles2 as gl;
+use sys::gles2::types::{GLbitfield, GLboolean, GLenum, GLint, GLsizei, GLuint};
+
+use gbm::Format;
+
+use crate::config;
+use crate::egl;
+use crate::error::{Error, Result};
+use crate::shader::ShaderProgram;
+
+type RenderbufferStorageMultisample = unsafe extern "system" fn(GLenum, GLsizei, GLenum, GLsizei, GLsizei);
+type BlitFramebuffer = unsafe extern "system" fn(
+    GLint, GLint, GLint, GLint,
+    GLint, GLint, GLint, GLint,
+    GLbitfield,
+    GLenum
+);
+type FramebufferTexture2DMultisampleEXT = unsafe extern "system" fn(GLenum, GLenum, GLenum, GLuint, GLint, GLsizei);
+
+const READ_FRAMEBUFFER: GLenum = 0x8CA8;
+const DRAW_FRAMEBUFFER: GLenum = 0x8CA9;
+const RGBA8: GLenum = 0x8058;
+const RGB10_A2: GLenum = 0x8059;
+const RGBA16F: GLenum = 0x881A;
+const HALF_FLOAT: GLenum = 0x140B;
+const UNSIGNED_INT_2_10_10_10_REV: GLenum = 0x8368;
+const MAX_SAMPLES: GLenum = 0x8D57;
+
+/// How the multisampled color buffer gets resolved.
+enum Resolve {
+    /// Single sampled, the texture is the color buffer.
+    Single,
+    /// GLES 3 multisampled renderbuffer, resolved into the texture with glBlitFramebuffer.
+    Blit { blit: BlitFramebuffer, renderbuffer: GLuint, resolve_fbo: GLuint },
+    /// EXT_multisampled_render_to_texture, the driver resolves into the texture implicitly.
+    RenderToTexture,
+}
+
+/// Color buffer of the framebuffer, as wide as the scanout format where GLES 3 can render to it.
+#[derive(Copy, Clone)]
+struct ColorFormat {
+    /// Internal format of the texture, unsized for RGBA8 as GLES 2 wants.
+    texture: GLenum,
+    renderbuffer: GLenum,
+    kind: GLenum,
+}
+
+const COLOR_RGBA8: ColorFormat = ColorFormat { texture: gl::RGBA, renderbuffer: RGBA8, kind: gl::UNSIGNED_BYTE };
+
+/// Picks the color buffer for the scanout `format`: RGB10_A2 for the 10 bit formats and
+/// RGBA16F for half float, which needs a color buffer float extension. Anything else, or a
+/// format GLES can't render to here, gets RGBA8.
+fn color_format(format: Format, gles3: bool, extensions: &str) -> ColorFormat {
+    let (color, renderable) = match format {
+        Format::Xrgb2101010 | Format::Xbgr2101010 => (
+            ColorFormat { texture: RGB10_A2, renderbuffer: RGB10_A2, kind: UNSIGNED_INT_2_10_10_10_REV },
+            gles3,
+        ),
+        Format::Xbgr16161616f | Format::Abgr16161616f => (
+            ColorFormat { texture: RGBA16F, renderbuffer: RGBA16F, kind: HALF_FLOAT },
+            gles3 && (extensions.contains("GL_EXT_color_buffer_half_float")
+                || extensions.contains("GL_EXT_color_buffer_float")),
+        ),
+        _ => return COLOR_RGBA8,
+    };
+
+    if renderable {
+        return color;
+    }
+    println!("no {} color buffer for the framebuffer, rendering at 8 bits per channel",
+        config::format_name(format));
+    COLOR_RGBA8
+}
+
+/// Framebuffer object the renderer draws into when the target can't be multisampled or has
+/// no depth buffer itself, resolved and copied into the target after every frame.
+///
+/// Only the EGL window surface can have samples from the config, so this covers GBM
+/// drivers without multisampled configs or without configs with depth, and offscreen
+/// rendering.
+pub struct Msaa {
+    resolve: Resolve,
+    fbo: GLuint,
+    /// Depth renderbuffer with as many samples as the color buffer, the EGL config may have
+    /// none.
+    depth: GLuint,
+    /// Single sampled copy of the frame, drawn into the target by the copy pass.
+    texture: GLuint,
+    program: Option<ShaderProgram>,
+    vbo: GLuint,
+    /// Framebuffer bound when this was created, where the frames end up.
+    target: GLuint,
+    width: u32,
+    height: u32,
+}
+
+impl Msaa {
+    /// Creates the framebuffer with up to `samples` samples, preferring a GLES 3 blit and
+    /// falling back to EXT_multisampled_render_to_texture. With 0 samples it's single
+    /// sampled, only bringing the depth buffer.
+    ///
+    /// The color buffer follows the scanout `format` as far as GLES allows, see `color_format`.
+    ///
+    /// Has to be called with the target framebuffer bound, before the renderer sets up its
+    /// program and vertex state.
+    pub fn new(width: u32, height: u32, samples: u32, format: Format) -> Result<Self> {
+        let mut target = 0;
+        let mut max_samples = 0;
+        unsafe {
+            gl::GetIntegerv(gl::FRAMEBUFFER_BINDING, &mut target);
+            gl::GetIntegerv(MAX_SAMPLES, &mut max_samples);
+        }
+        let samples = samples.min(max_samples.max(1) as u32) as GLsizei;
+
+        let gles3 = !egl::get_string(gl::VERSION).starts_with("OpenGL ES 2");
+        let extensions = egl::get_string(gl::EXTENSIONS);
+
+        let color = color_format(format, gles3, &extensions);
+        let texture = create_texture(width, height, color);
+        let mut fbo = 0;
+        unsafe {
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        }
+
+        let proc_address = |name| if gles3 { egl::proc_address(name) } else { None };
+        let mut depth = 0;
+        let attach_depth = |storage: RenderbufferStorageMultisample, depth: &mut GLuint| unsafe {
+            gl::GenRenderbuffers(1, depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, *depth);
+            storage(gl::RENDERBUFFER, samples, gl::DEPTH_COMPONENT16, width as GLsizei, height as GLsizei);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, *depth);
+        };
+
+        let resolve = match (proc_address("glRenderbufferStorageMultisample"), proc_address("glBlitFramebuffer")) {
+            _ if samples == 0 => {
+                unsafe {
+                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+                    gl::GenRenderbuffers(1, &mut depth);
+                    gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+                    gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT16, width as GLsizei, height as GLsizei);
+                    gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, depth);
+                }
+
+                Resolve::Single
+            }
+            (Some(storage), Some(blit)) => {
+                let storage: RenderbufferStorageMultisample = unsafe { std::mem::transmute(storage) };
+                let blit: BlitFramebuffer = unsafe { std::mem::transmute(blit) };
+
+                let mut renderbuffer = 0;
+                let mut resolve_fbo = 0;
+                unsafe {
+                    gl::GenRenderbuffers(1, &mut renderbuffer);
+                    gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+                    storage(gl::RENDERBUFFER, samples, color.renderbuffer, width as GLsizei, height as GLsizei);
+                    gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::RENDERBUFFER, renderbuffer);
+                }
+                attach_depth(storage, &mut depth);
+
+                unsafe {
+                    gl::GenFramebuffers(1, &mut resolve_fbo);
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, resolve_fbo);
+                    gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+                    gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+                }
+
+                Resolve::Blit { blit, renderbuffer, resolve_fbo }
+            }
+            _ => {
+                let functions = extensions.contains("GL_EXT_multisampled_render_to_texture")
+                    .then(|| egl::proc_address("glFramebufferTexture2DMultisampleEXT"))
+                    .flatten()
+                    .zip(egl::proc_address("glRenderbufferStorageMultisampleEXT"));
+                let (attach, storage) = match functions {
+                    Some((attach, storage)) => unsafe {
+                        (
+                            std::mem::transmute::<*const c_void, FramebufferTexture2DMultisampleEXT>(attach),
+                            std::mem::transmute::<*const c_void, RenderbufferStorageMultisample>(storage),
+                        )
+                    },
+                    None => {
+                        unsafe {
+                            gl::BindFramebuffer(gl::FRAMEBUFFER, target as GLuint);
+                            gl::DeleteFramebuffers(1, &fbo);
+                            gl::DeleteTextures(1, &texture);
+                        }
+                        return Err(Error::MissingExtension("GL_EXT_multisampled_render_to_texture"));
+                    }
+                };
+
+                unsafe { attach(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0, samples) };
+                attach_depth(storage, &mut depth);
+
+                Resolve::RenderToTexture
+            }
+        };
+
+        // created now so the objects are cleaned up on the early returns
+        let mut msaa = Msaa { resolve, fbo, depth, texture, program: None, vbo: 0, target: target as GLuint, width, height };
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, msaa.target) };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(Error::IncompleteFramebuffer(status));
+        }
+
+        let (program, vbo) = setup_copy()?;
+        msaa.program = Some(program);
+        msaa.vbo = vbo;
+
+        let method = match msaa.resolve {
+            Resolve::Single => None,
+            Resolve::Blit { .. } => Some("blit"),
+            Resolve::RenderToTexture => Some("EXT_multisampled_render_to_texture"),
+        };
+        match method {
+            Some(method) => println!("using a {}x multisampled framebuffer, resolved with {}", samples, method),
+            None => println!("using a framebuffer with a depth buffer, copied to the target"),
+        }
+
+        Ok(msaa)
+    }
+
+    /// Directs the following draws to the multisampled framebuffer.
+    pub fn bind(&self) {
+        unsafe { gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo) };
+    }
+
+    /// Resolves the frame and copies it into the target framebuffer, which is left bound.
+    ///
+    /// The GL state the copy pass touches is restored, so renderers can keep theirs bound
+    /// between frames.
+    pub fn resolve(&self) {
+        let (width, height) = (self.width as GLint, self.height as GLint);
+
+        if let Resolve::Blit { blit, resolve_fbo, .. } = self.resolve {
+            unsafe {
+                gl::BindFramebuffer(READ_FRAMEBUFFER, self.fbo);
+                gl::BindFramebuffer(DRAW_FRAMEBUFFER, resolve_fbo);
+                blit(0, 0, width, height, 0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+            }
+        }
+
+        let saved = SavedState::save();
+
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.target);
+            gl::Disable(gl::CULL_FACE);
+            gl::Disable(gl::DEPTH_TEST);
+
+            if let Some(ref program) = self.program {
+                program.bind();
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+            gl::BindTexture(gl::TEXTURE_2D, self.texture);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 0, std::ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::DrawArrays(gl::TRIANGLE_STRIP, 0, 4);
+        }
+
+        saved.restore();
+    }
+}
+
+/// Creates the framebuffer for `samples` samples when requested, printing why the frames go
+/// out without MSAA when neither method is available.
+///
+/// `depth` says the target has a depth buffer, without one a single sampled framebuffer
+/// brings it when there are no samples. `format` is the one the target is scanned out in.
+pub fn create(width: u32, height: u32, samples: u32, depth: bool, format: Format) -> Option<Msaa> {
+    if samples == 0 && depth {
+        return None;
+    }
+
+    let msaa = match Msaa::new(width, height, samples, format) {
+        Err(e) if samples > 0 => {
+            println!("no multisampled framebuffer ({}), rendering without MSAA", e);
+            if depth {
+                return None;
+            }
+            Msaa::new(width, height, 0, format)
+        }
+        result => result,
+    };
+
+    match msaa {
+        Ok(msaa) => Some(msaa),
+        Err(e) => {
+            println!("no framebuffer with a depth buffer ({}), rendering without depth testing", e);
+            None
+        }
+    }
+}
+
+impl Drop for Msaa {
+    fn drop(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.target);
+            if let Resolve::Blit { renderbuffer, resolve_fbo, .. } = self.resolve {
+                gl::DeleteFramebuffers(1, &resolve_fbo);
+                gl::DeleteRenderbuffers(1, &renderbuffer);
+            }
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteRenderbuffers(1, &self.depth);
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
+
+fn create_texture(width: u32, height: u32, color: ColorFormat) -> GLuint {
+    let mut texture = 0;
+
+    unsafe {
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            color.texture as i32,
+            width as i32,
+            height as i32,
+            0,
+            gl::RGBA,
+            color.kind,
+            std::ptr::null()
+        );
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+    }
+
+    texture
+}
+
+/// Builds the program and vertex buffer of the copy pass, a textured quad covering the
+/// viewport. Neither is left bound.
+fn setup_copy() -> Result<(ShaderProgram, GLuint)> {
+    let vertex_shader_source = r#"
+        attribute vec2 in_position;
+
+        varying vec2 vTexCoord;
+
+        void main()
+        {
+            vTexCoord = in_position * 0.5 + 0.5;
+            gl_Position = vec4(in_position, 0.0, 1.0);
+        }
+    "#;
+
+    let fragment_shader_source = r#"
+        precision mediump float;
+
+        uniform sampler2D uTexture;
+
+        varying vec2 vTexCoord;
+
+        void main()
+        {
+            gl_FragColor = texture2D(uTexture, vTexCoord);
+        }
+    "#;
+
+    let quad: [f32; 8] = [
+        -1.0, -1.0,
+        1.0, -1.0,
+        -1.0, 1.0,
+        1.0, 1.0,
+    ];
+
+    let program = ShaderProgram::new(vertex_shader_source, fragment_shader_source, &[(0, "in_position")])?;
+
+    let mut vbo = 0;
+    unsafe {
+        program.bind();
+        gl::Uniform1i(program.uniform_location("uTexture"), 0);
+        gl::UseProgram(0);
+
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(
+            gl::ARRAY_BUFFER,
+            std::mem::size_of_val(&quad) as isize,
+            quad.as_ptr() as *const _,
+            gl::STATIC_DRAW
+        );
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    Ok((program, vbo))
+}
+
+/// The GL state the copy pass changes.
+struct SavedState {
+    program: GLint,
+    array_buffer: GLint,
+    active_texture: GLint,
+    texture: GLint,
+    cull_face: GLboolean,
+    depth_test: GLboolean,
+    /// Enabled, size, type, normalized, stride and buffer of vertex attribute 0.
+    attrib: [GLint; 6],
+    attrib_pointer: *mut c_void,
+}
+
+impl SavedState {
+    fn save() -> Self {
+        let integer = |name| {
+            let mut value = 0;
+            unsafe { gl::GetIntegerv(name, &mut value) };
+            value
+        };
+        let attrib = |name| {
+            let mut value = 0;
+            unsafe { gl::GetVertexAttribiv(0, name, &mut value) };
+            value
+        };
+
+        // the generated binding takes the output pointer as const
+        let mut attrib_pointer = std::ptr::null_mut();
+        let out: *mut *mut c_void = &mut attrib_pointer;
+        unsafe { gl::GetVertexAttribPointerv(0, gl::VERTEX_ATTRIB_ARRAY_POINTER, out as *const _) };
+
+        SavedState {
+            program: integer(gl::CURRENT_PROGRAM),
+            array_buffer: integer(gl::ARRAY_BUFFER_BINDING),
+            active_texture: integer(gl::ACTIVE_TEXTURE),
+            texture: {
+                unsafe { gl::ActiveTexture(gl::TEXTURE0) };
+                integer(gl::TEXTURE_BINDING_2D)
+            },
+            cull_face: unsafe { gl::IsEnabled(gl::CULL_FACE) },
+            depth_test: unsafe { gl::IsEnabled(gl::DEPTH_TEST) },
+            attrib: [
+                attrib(gl::VERTEX_ATTRIB_ARRAY_ENABLED),
+                attrib(gl::VERTEX_ATTRIB_ARRAY_SIZE),
+                attrib(gl::VERTEX_ATTRIB_ARRAY_TYPE),
+                attrib(gl::VERTEX_ATTRIB_ARRAY_NORMALIZED),
+                attrib(gl::VERTEX_ATTRIB_ARRAY_STRIDE),
+                attrib(gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING),
+            ],
+            attrib_pointer,
+        }
+    }
+
+    fn restore(&self) {
+        let enable = |cap, enabled| unsafe {
+            if enabled == gl::TRUE { gl::Enable(cap) } else { gl::Disable(cap) }
+        };
+
+        let [enabled, size, kind, normalized, stride, buffer] = self.attrib;
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, buffer as GLuint);
+            gl::VertexAttribPointer(0, size, kind as GLenum, normalized as GLboolean, stride, self.attrib_pointer);
+            if enabled == 0 {
+                gl::DisableVertexAttribArray(0);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.array_buffer as GLuint);
+
+            gl::BindTexture(gl::TEXTURE_2D, self.texture as GLuint);
+            gl::ActiveTexture(self.active_texture as GLenum);
+            gl::UseProgram(self.program as GLuint);
+        }
+        enable(gl::CULL_FACE, self.cull_face);
+        enable(gl::DEPTH_TEST, self.depth_test);
+    }
+}
@@ -0,0 +1,74 @@
+use sys::gles2 as gl;
+
+use crate::error::{Error, Result};
+
+/// Framebuffer object with an RGBA8 texture and a depth renderbuffer attached, the render
+/// target when there is no window surface.
+pub struct Offscreen {
+    fbo: u32,
+    texture: u32,
+    depth: u32,
+    width: u32,
+    height: u32,
+}
+
+impl Offscreen {
+    /// Creates the framebuffer object and binds it, so following draws go to it.
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        let mut fbo = 0;
+        let mut texture = 0;
+        let mut depth = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                std::ptr::null()
+            );
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+
+            gl::GenRenderbuffers(1, &mut depth);
+            gl::BindRenderbuffer(gl::RENDERBUFFER, depth);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT16, width as i32, height as i32);
+
+            gl::GenFramebuffers(1, &mut fbo);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, texture, 0);
+            gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::RENDERBUFFER, depth);
+        }
+
+        let offscreen = Offscreen { fbo, texture, depth, width, height };
+
+        let status = unsafe { gl::CheckFramebufferStatus(gl::FRAMEBUFFER) };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            return Err(Error::IncompleteFramebuffer(status));
+        }
+
+        Ok(offscreen)
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Drop for Offscreen {
+    fn drop(&mut self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteRenderbuffers(1, &self.depth);
+            gl::DeleteTextures(1, &self.texture);
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use gbm::Format;
+
+use kmscube::capture::Capture;
+use kmscube::config::{self, ConnectorSelector, DisplayConfig, VideoMode};
+use kmscube::hot_reload::ShaderFiles;
+use kmscube::renderer;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub device: String,
+    pub connector: Option<ConnectorSelector>,
+    pub video_mode: Option<VideoMode>,
+    pub format: Format,
+    pub modifier: Option<u64>,
+    /// Force `DRM_FORMAT_MOD_LINEAR`, overriding `modifier`.
+    pub linear: bool,
+    pub samples: u32,
+    pub count: Option<u32>,
+    pub duration: Option<Duration>,
+    /// Name of the renderer, see `renderer::by_name`.
+    pub mode: String,
+    pub atomic: bool,
+    pub soak: bool,
+    pub perf: bool,
+    pub perf_report: Option<String>,
+    pub surfaceless: bool,
+    pub capture_dir: String,
+    pub capture_frames: Vec<u32>,
+    pub capture_every: Option<u32>,
+    /// Shader files replacing the smooth cube's built in ones, reloaded when they change.
+    pub vertex_shader: Option<String>,
+    pub fragment_shader: Option<String>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            device: "/dev/dri/card0".to_owned(),
+            connector: None,
+            video_mode: None,
+            format: Format::Xrgb8888,
+            modifier: None,
+            linear: false,
+            samples: 0,
+            count: None,
+            duration: None,
+            mode: "smooth".to_owned(),
+            atomic: false,
+            soak: false,
+            perf: false,
+            perf_report: None,
+            surfaceless: false,
+            capture_dir: ".".to_owned(),
+            capture_frames: Vec::new(),
+            capture_every: None,
+            vertex_shader: None,
+            fragment_shader: None,
+        }
+    }
+}
+
+pub fn usage(name: &str) -> String {
+    format!("Usage: {} [-ACDfhLMmpSstvx]
+
+options:
+    -A, --atomic             use atomic modesetting, falls back to legacy
+    -c, --count=N            run for the specified number of frames
+        --capture=FRAMES     write the given comma separated frames as PNG
+        --capture-every=N    write every Nth frame as PNG
+        --capture-dir=DIR    directory for captured frames (default: .)
+    -C, --connector=CONN     use the given connector, by name (e.g. HDMI-A-1) or
+                             by index
+    -D, --device=DEVICE      use the given device (default: /dev/dri/card0)
+        --fragment-shader=FILE
+                             use the fragment shader in FILE, see --vertex-shader
+    -f, --format=FOURCC      framebuffer format (XR24, XB24, AR24, AB24, RG16, XR30,
+                             XB30, XB4H), falls back to XR24 if the plane or EGL
+                             can't use it
+    -h, --help               print usage
+    -L, --linear             force the LINEAR modifier, for debugging scanout
+                             of tiled or compressed buffers
+    -M, --mode=MODE          specify mode, one of:
+        smooth  -  smooth shaded cube (default)
+        tex     -  texture mapped cube (alias: rgba)
+    -m, --modifier=MODIFIER  hardcode the selected modifier instead of picking one
+                             the plane and renderer both support
+    -p, --perf               print FPS and frame, draw and swap times every 5 s
+        --perf-report=FILE   write per frame timings at exit, as CSV if FILE ends
+                             in .csv, JSON otherwise; implies --perf
+    -s, --samples=N          use N samples of MSAA, from the EGL config if possible,
+                             else from a multisampled framebuffer
+    -t, --seconds=T          run for T seconds, together with --count whichever
+                             comes first
+    -S, --soak=N             run N thousand frames and report how many
+                             framebuffers were created and are still alive
+        --vertex-shader=FILE
+                             use the vertex shader in FILE instead of the built in
+                             one (smooth mode only) and rebuild the program whenever
+                             the file changes, keeping the previous one on errors
+    -v, --vmode=VMODE        specify the video mode in the format
+                             <mode>[@<vrefresh>], e.g. 1920x1080@60 (default:
+                             the preferred mode, else the largest one)
+    -x, --surfaceless        render offscreen without modesetting, the size is
+                             taken from --vmode (default: 640x480)", name)
+}
+
+impl Options {
+    /// Parses the command line, `args` includes the program name.
+    ///
+    /// Returns `Ok(None)` when only the usage was requested.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Option<Options>, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter().skip(1);
+
+        while let Some(arg) = args.next() {
+            let (flag, inline_value) = split_arg(&arg)?;
+
+            if flag == "h" || flag == "help" {
+                return Ok(None);
+            }
+
+            let switch = match flag.as_str() {
+                "A" | "atomic" => Some(&mut options.atomic),
+                "L" | "linear" => Some(&mut options.linear),
+                "p" | "perf" => Some(&mut options.perf),
+                "x" | "surfaceless" => Some(&mut options.surfaceless),
+                _ => None,
+            };
+            if let Some(switch) = switch {
+                if inline_value.is_some() {
+                    return Err(format!("option '{}' doesn't take a value", arg));
+                }
+                *switch = true;
+                continue;
+            }
+
+            let value = match inline_value {
+                Some(value) => value,
+                None => args.next().ok_or_else(|| format!("option '{}' requires a value", arg))?,
+            };
+
+            match flag.as_str() {
+                "c" | "count" => options.count = Some(parse_number(&flag, &value)?),
+                "t" | "seconds" => options.duration = Some(parse_duration(&flag, &value)?),
+                "capture" => options.capture_frames = value.split(',')
+                    .map(|frame| parse_number(&flag, frame.trim()))
+                    .collect::<Result<_, _>>()?,
+                "capture-every" => options.capture_every = Some(parse_number(&flag, &value)?),
+                "capture-dir" => options.capture_dir = value,
+                "C" | "connector" => options.connector = Some(parse_connector(&value)?),
+                "D" | "device" => options.device = value,
+                "perf-report" => options.perf_report = Some(value),
+                "vertex-shader" => options.vertex_shader = Some(value),
+                "fragment-shader" => options.fragment_shader = Some(value),
+                "f" | "format" => options.format = parse_format(&value)?,
+                "M" | "mode" => options.mode = parse_demo_mode(&value)?,
+                "m" | "modifier" => options.modifier = Some(parse_modifier(&value)?),
+                "s" | "samples" => options.samples = parse_number(&flag, &value)?,
+                "S" | "soak" => {
+                    let thousands: u32 = parse_number(&flag, &value)?;
+                    options.soak = true;
+                    options.count = Some(thousands.saturating_mul(1000));
+                }
+                "v" | "vmode" => options.video_mode = Some(parse_video_mode(&value)?),
+                _ => return Err(format!("unknown option '{}'", arg)),
+            }
+        }
+
+        // only the smooth cube takes its shaders from files
+        if options.mode != "smooth" {
+            if options.vertex_shader.is_some() {
+                return Err(format!("option '--vertex-shader' needs mode 'smooth', not '{}'", options.mode));
+            }
+            if options.fragment_shader.is_some() {
+                return Err(format!("option '--fragment-shader' needs mode 'smooth', not '{}'", options.mode));
+            }
+        }
+
+        Ok(Some(options))
+    }
+
+    /// What the library needs to set up the output and run the frame loop.
+    pub fn display_config(&self) -> DisplayConfig {
+        let capture = if self.capture_frames.is_empty() && self.capture_every.is_none() {
+            None
+        } else {
+            Some(Capture::new(&self.capture_dir, self.capture_frames.clone(), self.capture_every))
+        };
+
+        DisplayConfig {
+            device: self.device.clone(),
+            connector: self.connector.clone(),
+            video_mode: self.video_mode.clone(),
+            format: self.format,
+            modifier: self.modifier,
+            linear: self.linear,
+            samples: self.samples,
+            atomic: self.atomic,
+            perf: self.perf,
+            perf_report: self.perf_report.clone(),
+            capture,
+            soak: self.soak,
+        }
+    }
+
+    /// `None` unless `--vertex-shader` or `--fragment-shader` was given.
+    pub fn shader_files(&self) -> Option<ShaderFiles> {
+        if self.vertex_shader.is_none() && self.fragment_shader.is_none() {
+            return None;
+        }
+
+        Some(ShaderFiles::new(
+            self.vertex_shader.as_ref().map(PathBuf::from),
+            self.fragment_shader.as_ref().map(PathBuf::from),
+        ))
+    }
+}
+
+/// Splits `-x`, `-xVALUE`, `--long` and `--long=VALUE` into the flag name and the inline value.
+fn split_arg(arg: &str) -> Result<(String, Option<String>), String> {
+    if let Some(long) = arg.strip_prefix("--") {
+        let mut parts = long.splitn(2, '=');
+        let flag = parts.next().unwrap_or("").to_owned();
+        Ok((flag, parts.next().map(str::to_owned)))
+    } else if arg.starts_with('-') && arg.len() > 1 {
+        let mut chars = arg[1..].chars();
+        let flag = chars.next().unwrap().to_string();
+        let rest = chars.as_str();
+        Ok((flag, if rest.is_empty() { None } else { Some(rest.to_owned()) }))
+    } else {
+        Err(format!("unexpected argument '{}'", arg))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("invalid value '{}' for option '{}'", value, flag))
+}
+
+fn parse_duration(flag: &str, value: &str) -> Result<Duration, String> {
+    let seconds: f64 = parse_number(flag, value)?;
+    if !seconds.is_finite() || seconds <= 0.0 {
+        return Err(format!("invalid value '{}' for option '{}'", value, flag));
+    }
+
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+fn parse_format(value: &str) -> Result<Format, String> {
+    config::FORMATS.iter()
+        .find(|(fourcc, _)| *fourcc == value)
+        .map(|&(_, format)| format)
+        .ok_or_else(|| format!("invalid format: {}", value))
+}
+
+fn parse_demo_mode(value: &str) -> Result<String, String> {
+    if !renderer::exists(value) {
+        return Err(format!("invalid mode: {}", value));
+    }
+
+    Ok(value.to_owned())
+}
+
+fn parse_modifier(value: &str) -> Result<u64, String> {
+    let parsed = if value.starts_with("0x") || value.starts_with("0X") {
+        u64::from_str_radix(&value[2..], 16)
+    } else {
+        value.parse()
+    };
+
+    parsed.map_err(|_| format!("invalid modifier: {}", value))
+}
+
+fn parse_connector(value: &str) -> Result<ConnectorSelector, String> {
+    if value.is_empty() {
+        return Err("invalid connector: empty name".to_owned());
+    }
+
+    Ok(match value.parse() {
+        Ok(index) => ConnectorSelector::Index(index),
+        Err(_) => ConnectorSelector::Name(value.to_owned()),
+    })
+}
+
+/// Accepts `<mode>@<vrefresh>` and, like kmscube, `<mode>-<vrefresh>`.
+fn parse_video_mode(value: &str) -> Result<VideoMode, String> {
+    let (name, vrefresh) = match value.find(['@', '-']) {
+        Some(at) => (&value[..at], Some(&value[at + 1..])),
+        None => (value, None),
+    };
+    if name.is_empty() {
+        return Err(format!("invalid video mode: {}", value));
+    }
+
+    let vrefresh = match vrefresh {
+        Some(vrefresh) => Some(vrefresh.parse().map_err(|_| format!("invalid video mode: {}", value))?),
+        None => None,
+    };
+
+    Ok(VideoMode { name: name.to_owned(), vrefresh })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Option<Options>, String> {
+        Options::parse(std::iter::once("kmscube-rs").chain(args.iter().cloned()).map(str::to_owned))
+    }
+
+    fn video_mode(name: &str, vrefresh: Option<u32>) -> VideoMode {
+        VideoMode { name: name.to_owned(), vrefresh }
+    }
+
+    #[test]
+    fn video_mode_with_or_without_refresh() {
+        assert_eq!(parse_video_mode("1920x1080"), Ok(video_mode("1920x1080", None)));
+        assert_eq!(parse_video_mode("1920x1080@60"), Ok(video_mode("1920x1080", Some(60))));
+        assert_eq!(parse_video_mode("1920x1080-60"), Ok(video_mode("1920x1080", Some(60))));
+    }
+
+    #[test]
+    fn video_mode_errors() {
+        assert!(parse_video_mode("").is_err());
+        assert!(parse_video_mode("@60").is_err());
+        assert!(parse_video_mode("1920x1080@").is_err());
+        assert!(parse_video_mode("1920x1080@sixty").is_err());
+        assert!(parse_video_mode("1920x1080-60.5").is_err());
+    }
+
+    #[test]
+    fn connector_by_index_or_name() {
+        assert_eq!(parse_connector("1"), Ok(ConnectorSelector::Index(1)));
+        assert_eq!(parse_connector("HDMI-A-1"), Ok(ConnectorSelector::Name("HDMI-A-1".to_owned())));
+        assert!(parse_connector("").is_err());
+    }
+
+    #[test]
+    fn modifier_in_hex_or_decimal() {
+        assert_eq!(parse_modifier("0x0100000000000001"), Ok(0x0100_0000_0000_0001));
+        assert_eq!(parse_modifier("0XFF"), Ok(0xff));
+        assert_eq!(parse_modifier("72057594037927937"), Ok(0x0100_0000_0000_0001));
+        assert!(parse_modifier("0x").is_err());
+        assert!(parse_modifier("0xfg").is_err());
+        assert!(parse_modifier("ff").is_err());
+    }
+
+    #[test]
+    fn split_short_and_long_forms() {
+        assert_eq!(split_arg("-c"), Ok(("c".to_owned(), None)));
+        assert_eq!(split_arg("-c10"), Ok(("c".to_owned(), Some("10".to_owned()))));
+        assert_eq!(split_arg("--count"), Ok(("count".to_owned(), None)));
+        assert_eq!(split_arg("--count=10"), Ok(("count".to_owned(), Some("10".to_owned()))));
+        assert_eq!(split_arg("--capture=1,2"), Ok(("capture".to_owned(), Some("1,2".to_owned()))));
+        assert!(split_arg("count").is_err());
+        assert!(split_arg("-").is_err());
+    }
+
+    #[test]
+    fn values_inline_or_separate() {
+        for args in [&["-c10"][..], &["-c", "10"], &["--count=10"], &["--count", "10"]] {
+            assert_eq!(parse(args).unwrap().unwrap().count, Some(10), "{:?}", args);
+        }
+
+        let options = parse(&["-v1280x720@50", "--connector=HDMI-A-1", "-m0x1"]).unwrap().unwrap();
+        assert_eq!(options.video_mode, Some(video_mode("1280x720", Some(50))));
+        assert_eq!(options.connector, Some(ConnectorSelector::Name("HDMI-A-1".to_owned())));
+        assert_eq!(options.modifier, Some(1));
+    }
+
+    #[test]
+    fn switches_take_no_value() {
+        assert!(parse(&["-A", "-x"]).unwrap().unwrap().atomic);
+        assert!(parse(&["--atomic=yes"]).is_err());
+        assert!(parse(&["-Ayes"]).is_err());
+    }
+
+    #[test]
+    fn missing_and_unknown_options() {
+        assert!(parse(&["--count"]).is_err());
+        assert!(parse(&["--bogus=1"]).is_err());
+        assert!(parse(&["-v", "@60"]).is_err());
+        assert!(parse(&["--help"]).unwrap().is_none());
+    }
+}
@@ -0,0 +1,192 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::config::DisplayConfig;
+
+/// How often the running statistics are printed.
+const PRINT_INTERVAL: Duration = Duration::from_secs(5);
+
+struct FrameTiming {
+    /// CPU time spent in the draw callback.
+    draw: Duration,
+    /// CPU time spent in eglSwapBuffers.
+    swap: Duration,
+    /// When the frame reached the screen: the page flip event timestamp, or the time since
+    /// the start of the run when there is no display.
+    presented: Duration,
+}
+
+/// Minimum, average and 99th percentile in milliseconds.
+struct Summary {
+    min: f64,
+    avg: f64,
+    p99: f64,
+}
+
+impl Summary {
+    fn new(mut values: Vec<f64>) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let rank = (values.len() as f64 * 0.99).ceil() as usize;
+
+        Some(Summary {
+            min: values[0],
+            avg: values.iter().sum::<f64>() / values.len() as f64,
+            p99: values[rank.max(1) - 1],
+        })
+    }
+
+    fn json(&self) -> String {
+        format!("{{\"min\": {:.3}, \"avg\": {:.3}, \"p99\": {:.3}}}", self.min, self.avg, self.p99)
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "min {:.3} / avg {:.3} / p99 {:.3} ms", self.min, self.avg, self.p99)
+    }
+}
+
+fn ms(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// Per frame timings for `--perf`.
+///
+/// Does nothing unless enabled, so the frame loops can record unconditionally.
+pub struct Perf {
+    enabled: bool,
+    report: Option<String>,
+    start: Instant,
+    frames: Vec<FrameTiming>,
+    /// First frame and time of the current print interval.
+    interval: (usize, Instant),
+}
+
+impl Perf {
+    pub fn new(config: &DisplayConfig) -> Self {
+        let now = Instant::now();
+
+        Perf {
+            enabled: config.perf || config.perf_report.is_some(),
+            report: config.perf_report.clone(),
+            start: now,
+            frames: Vec::new(),
+            interval: (0, now),
+        }
+    }
+
+    /// Records a frame, `flip` is the page flip timestamp if the frame went to a display.
+    pub fn record(&mut self, draw: Duration, swap: Duration, flip: Option<Duration>) {
+        if !self.enabled {
+            return;
+        }
+
+        let presented = flip.unwrap_or_else(|| self.start.elapsed());
+        self.frames.push(FrameTiming { draw, swap, presented });
+
+        let (first, since) = self.interval;
+        if since.elapsed() >= PRINT_INTERVAL {
+            self.print(first, since.elapsed());
+            self.interval = (self.frames.len(), Instant::now());
+        }
+    }
+
+    /// Frame times are the intervals between consecutive presentations, the one of frame
+    /// `first` is measured from the frame before it.
+    fn frame_times(&self, first: usize) -> Vec<f64> {
+        let first = first.saturating_sub(1);
+        self.frames[first..].windows(2)
+            .map(|pair| ms(pair[1].presented.saturating_sub(pair[0].presented)))
+            .collect()
+    }
+
+    fn print(&self, first: usize, elapsed: Duration) {
+        let frames = &self.frames[first..];
+        let fps = frames.len() as f64 / elapsed.as_secs_f64();
+
+        print!("perf: {} frames in {:.1} s ({:.2} fps)", frames.len(), elapsed.as_secs_f64(), fps);
+        if let Some(frame_time) = Summary::new(self.frame_times(first)) {
+            print!(", frame time {}", frame_time);
+        }
+        if let Some(draw) = Summary::new(frames.iter().map(|f| ms(f.draw)).collect()) {
+            print!(", draw {}", draw);
+        }
+        if let Some(swap) = Summary::new(frames.iter().map(|f| ms(f.swap)).collect()) {
+            print!(", swap {}", swap);
+        }
+        println!();
+    }
+
+    /// Prints the statistics of the whole run and writes the report if one was requested.
+    pub fn finish(&self) {
+        if !self.enabled || self.frames.is_empty() {
+            return;
+        }
+
+        let elapsed = self.start.elapsed();
+        self.print(0, elapsed);
+
+        if let Some(ref path) = self.report {
+            match self.write_report(Path::new(path), elapsed) {
+                Ok(()) => println!("perf: report written to {}", path),
+                Err(e) => eprintln!("failed to write perf report {}: {}", path, e),
+            }
+        }
+    }
+
+    fn write_report(&self, path: &Path, elapsed: Duration) -> io::Result<()> {
+        let mut file = BufWriter::new(File::create(path)?);
+
+        if path.extension().map_or(false, |ext| ext == "csv") {
+            self.write_csv(&mut file)?;
+        } else {
+            self.write_json(&mut file, elapsed)?;
+        }
+
+        file.flush()
+    }
+
+    fn write_csv(&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "frame,draw_ms,swap_ms,presented_s,frame_time_ms")?;
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let frame_time = match i {
+                0 => String::new(),
+                _ => format!("{:.3}", ms(frame.presented.saturating_sub(self.frames[i - 1].presented))),
+            };
+            writeln!(out, "{},{:.3},{:.3},{:.6},{}",
+                i, ms(frame.draw), ms(frame.swap), frame.presented.as_secs_f64(), frame_time)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_json(&self, out: &mut dyn Write, elapsed: Duration) -> io::Result<()> {
+        let summary = |values: Vec<f64>| Summary::new(values).map_or("null".to_owned(), |s| s.json());
+        let elapsed = elapsed.as_secs_f64();
+
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"frames\": {},", self.frames.len())?;
+        writeln!(out, "  \"seconds\": {:.3},", elapsed)?;
+        writeln!(out, "  \"fps\": {:.2},", self.frames.len() as f64 / elapsed)?;
+        writeln!(out, "  \"frame_time_ms\": {},", summary(self.frame_times(0)))?;
+        writeln!(out, "  \"draw_ms\": {},", summary(self.frames.iter().map(|f| ms(f.draw)).collect()))?;
+        writeln!(out, "  \"swap_ms\": {},", summary(self.frames.iter().map(|f| ms(f.swap)).collect()))?;
+        writeln!(out, "  \"per_frame\": [")?;
+
+        for (i, frame) in self.frames.iter().enumerate() {
+            let separator = if i + 1 < self.frames.len() { "," } else { "" };
+            writeln!(out, "    {{\"frame\": {}, \"draw_ms\": {:.3}, \"swap_ms\": {:.3}, \"presented_s\": {:.6}}}{}",
+                i, ms(frame.draw), ms(frame.swap), frame.presented.as_secs_f64(), separator)?;
+        }
+
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
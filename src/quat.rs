@@ -0,0 +1,143 @@
+use std::f32::consts::PI;
+use std::ops::Mul;
+
+use crate::es_matrix::ESMatrix;
+use crate::vector::Vec3;
+
+/// Below this angle between two orientations `slerp` interpolates linearly, the sine it
+/// divides by gets too small.
+const SLERP_LINEAR_COS: f32 = 0.9995;
+
+/// Rotation quaternion, `w` is the real part.
+///
+/// Rotations follow the right hand rule: counter-clockwise when looking down the axis
+/// towards the origin.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::identity()
+    }
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Quat {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Quat {
+        Quat { x: 0.0, y: 0.0, z: 0.0, w: 1.0 }
+    }
+
+    /// Rotation by `angle` degrees around `axis`, the identity if the axis is zero.
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Quat {
+        let axis = match axis.normalize() {
+            Some(axis) => axis,
+            None => return Quat::identity(),
+        };
+
+        let half = angle * PI / 360.0;
+        let s = half.sin();
+
+        Quat { x: axis.x * s, y: axis.y * s, z: axis.z * s, w: half.cos() }
+    }
+
+    pub fn dot(self, other: Quat) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Scales back to unit length, rounding errors pile up when composing many rotations.
+    pub fn normalize(self) -> Quat {
+        let length = self.length();
+        if length == 0.0 {
+            return Quat::identity();
+        }
+
+        Quat { x: self.x / length, y: self.y / length, z: self.z / length, w: self.w / length }
+    }
+
+    /// The inverse rotation, for unit quaternions.
+    pub fn conjugate(self) -> Quat {
+        Quat { x: -self.x, y: -self.y, z: -self.z, w: self.w }
+    }
+
+    /// Interpolates between two orientations at constant angular speed, `t` runs from 0 at
+    /// `self` to 1 at `other`. Takes the shorter way around.
+    pub fn slerp(self, other: Quat, t: f32) -> Quat {
+        let mut cos = self.dot(other);
+        // q and -q are the same orientation, flip one so the arc is at most 180°
+        let other = if cos < 0.0 {
+            cos = -cos;
+            Quat { x: -other.x, y: -other.y, z: -other.z, w: -other.w }
+        } else {
+            other
+        };
+
+        let (a, b) = if cos > SLERP_LINEAR_COS {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Quat {
+            x: a * self.x + b * other.x,
+            y: a * self.y + b * other.y,
+            z: a * self.z + b * other.z,
+            w: a * self.w + b * other.w,
+        }.normalize()
+    }
+
+    /// The rotation as a matrix, in `ESMatrix`'s transposed storage.
+    pub fn to_matrix(self) -> ESMatrix {
+        let Quat { x, y, z, w } = self.normalize();
+        let (xx, yy, zz) = (x * x, y * y, z * z);
+        let (xy, yz, zx) = (x * y, y * z, z * x);
+        let (xw, yw, zw) = (x * w, y * w, z * w);
+
+        ESMatrix::new([
+            [1.0 - 2.0 * (yy + zz), 2.0 * (xy + zw), 2.0 * (zx - yw), 0.0],
+            [2.0 * (xy - zw), 1.0 - 2.0 * (xx + zz), 2.0 * (yz + xw), 0.0],
+            [2.0 * (zx + yw), 2.0 * (yz - xw), 1.0 - 2.0 * (xx + yy), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+/// Composes the rotations, `a * b` applies `b` first.
+impl Mul for Quat {
+    type Output = Quat;
+
+    fn mul(self, o: Quat) -> Quat {
+        Quat {
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+        }
+    }
+}
+
+/// Rotates the vector.
+impl Mul<Vec3> for Quat {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        let q = Vec3::new(self.x, self.y, self.z);
+        // v + 2w (q x v) + 2 q x (q x v), the expansion of q v q*
+        let t = q.cross(v) * 2.0;
+
+        v + t * self.w + q.cross(t)
+    }
+}
@@ -0,0 +1,74 @@
+use std::ops::{Deref, DerefMut};
+use std::time::Duration;
+
+use gbm::Format;
+
+use crate::cube_smooth::CubeSmooth;
+use crate::cube_tex::CubeTex;
+use crate::error::Result;
+use crate::hot_reload::ShaderFiles;
+
+/// A demo drawing into the current GL context.
+///
+/// Implementations own their GL objects: they are created in `init` and deleted in
+/// `teardown`, both called with the context current.
+pub trait Renderer {
+    /// Creates the GL state for a `width`x`height` surface in `format`.
+    fn init(&mut self, width: u32, height: u32, format: Format) -> Result<()>;
+
+    /// Draws `frame`, `time` has passed since the first one.
+    fn draw(&mut self, frame: u32, time: Duration);
+
+    fn resize(&mut self, width: u32, height: u32);
+
+    fn teardown(&mut self);
+}
+
+/// Tears the renderer down when dropped, so its GL objects are deleted on every way out of
+/// the frame loop. Has to go before the context does.
+pub struct TeardownGuard<'a>(&'a mut dyn Renderer);
+
+impl<'a> TeardownGuard<'a> {
+    pub fn new(renderer: &'a mut dyn Renderer) -> Self {
+        TeardownGuard(renderer)
+    }
+}
+
+impl<'a> Deref for TeardownGuard<'a> {
+    type Target = dyn Renderer + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+impl<'a> DerefMut for TeardownGuard<'a> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.0
+    }
+}
+
+impl<'a> Drop for TeardownGuard<'a> {
+    fn drop(&mut self) {
+        self.0.teardown();
+    }
+}
+
+type Constructor = fn(Option<ShaderFiles>) -> Box<dyn Renderer>;
+
+/// Renderers by name, the binary's `--mode`.
+const RENDERERS: &[(&str, Constructor)] = &[
+    ("smooth", |shader_files| Box::new(CubeSmooth::new(shader_files))),
+    ("tex", |_| Box::<CubeTex>::default()),
+    ("rgba", |_| Box::<CubeTex>::default()),
+];
+
+pub fn exists(name: &str) -> bool {
+    RENDERERS.iter().any(|&(n, _)| n == name)
+}
+
+/// Returns the renderer registered as `name`, not initialized yet. `shader_files` replace
+/// the built in shaders of renderers that support it and are ignored by the others.
+pub fn by_name(name: &str, shader_files: Option<ShaderFiles>) -> Option<Box<dyn Renderer>> {
+    RENDERERS.iter().find(|&&(n, _)| n == name).map(|&(_, create)| create(shader_files))
+}
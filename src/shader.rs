@@ -0,0 +1,220 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use sys::gles2 as gl;
+use sys::gles2::types::{GLenum, GLint, GLuint};
+
+use crate::error::{Error, Result};
+
+/// Linked program with its uniform locations cached by name. The context it was created in
+/// has to be current when it's used and when it's dropped, which deletes it.
+pub struct ShaderProgram {
+    id: GLuint,
+    uniforms: RefCell<HashMap<String, GLint>>,
+}
+
+impl ShaderProgram {
+    /// Compiles both stages, binds each attribute name to its location and links.
+    ///
+    /// Compile errors come with the offending source lines, logs of a successful build are
+    /// printed since they usually hold warnings.
+    pub fn new(vertex_source: &str, fragment_source: &str, attributes: &[(GLuint, &str)]) -> Result<Self> {
+        let attributes = attributes
+            .iter()
+            .map(|&(location, name)| match CString::new(name) {
+                Ok(s) => Ok((location, s)),
+                Err(_) => Err(Error::ProgramLink { log: format!("attribute name {:?} contains a nul byte", name) }),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let vertex_shader = compile_shader("vertex", gl::VERTEX_SHADER, vertex_source)?;
+        let fragment_shader = match compile_shader("fragment", gl::FRAGMENT_SHADER, fragment_source) {
+            Ok(shader) => shader,
+            Err(e) => {
+                unsafe { gl::DeleteShader(vertex_shader) };
+                return Err(e);
+            }
+        };
+
+        let id = unsafe { gl::CreateProgram() };
+
+        unsafe {
+            gl::AttachShader(id, vertex_shader);
+            gl::AttachShader(id, fragment_shader);
+
+            for (location, name) in &attributes {
+                gl::BindAttribLocation(id, *location, name.as_ptr());
+            }
+
+            gl::LinkProgram(id);
+
+            // the program keeps what it needs, the shaders aren't used again
+            gl::DetachShader(id, vertex_shader);
+            gl::DetachShader(id, fragment_shader);
+            gl::DeleteShader(vertex_shader);
+            gl::DeleteShader(fragment_shader);
+        }
+
+        let mut ret = 0;
+        unsafe { gl::GetProgramiv(id, gl::LINK_STATUS, &mut ret) };
+        let log = program_info_log(id);
+        if ret == 0 {
+            unsafe { gl::DeleteProgram(id) };
+            return Err(Error::ProgramLink { log });
+        }
+        if !log.trim().is_empty() {
+            println!("program link log:\n{}", log);
+        }
+
+        Ok(ShaderProgram { id, uniforms: RefCell::new(HashMap::new()) })
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    /// Makes this the current program.
+    pub fn bind(&self) {
+        unsafe { gl::UseProgram(self.id) };
+    }
+
+    /// Location of the uniform `name`, -1 if the program has no such active uniform, which
+    /// includes names with a nul byte. Only the first lookup of a name queries GL.
+    pub fn uniform_location(&self, name: &str) -> GLint {
+        if let Some(&location) = self.uniforms.borrow().get(name) {
+            return location;
+        }
+
+        let location = match CString::new(name) {
+            Ok(s) => unsafe { gl::GetUniformLocation(self.id, s.as_ptr()) },
+            Err(_) => -1,
+        };
+        self.uniforms.borrow_mut().insert(name.to_owned(), location);
+
+        location
+    }
+}
+
+impl Drop for ShaderProgram {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteProgram(self.id) };
+    }
+}
+
+fn compile_shader(stage: &'static str, kind: GLenum, source: &str) -> Result<GLuint> {
+    let s = match CString::new(source.as_bytes()) {
+        Ok(s) => s,
+        Err(e) => {
+            // reported like a compiler would, so it gets the offending line too
+            let line = source[..e.nul_position()].matches('\n').count() + 1;
+            let log = format!("0:{}: error: source contains a nul byte\n", line);
+            return Err(Error::ShaderCompile { stage, log: annotate_log(&log, source) });
+        }
+    };
+
+    let mut ret = 0;
+
+    let shader = unsafe { gl::CreateShader(kind) };
+
+    unsafe {
+        gl::ShaderSource(shader, 1, &s.as_ptr(), std::ptr::null());
+        gl::CompileShader(shader);
+        gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut ret);
+    }
+
+    let log = annotate_log(&shader_info_log(shader), source);
+    if ret == 0 {
+        unsafe { gl::DeleteShader(shader) };
+        return Err(Error::ShaderCompile { stage, log });
+    }
+    if !log.trim().is_empty() {
+        println!("{} shader log:\n{}", stage, log);
+    }
+
+    Ok(shader)
+}
+
+fn info_log(log: &[u8]) -> String {
+    let end = log.iter().position(|&c| c == 0).unwrap_or(log.len());
+    String::from_utf8_lossy(&log[..end]).into_owned()
+}
+
+fn shader_info_log(shader: GLuint) -> String {
+    let mut len = 0;
+    unsafe { gl::GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut len) };
+    if len <= 1 {
+        return String::new();
+    }
+
+    let mut log = vec![0_u8; len as usize];
+    unsafe { gl::GetShaderInfoLog(shader, len, std::ptr::null_mut(), log.as_mut_ptr() as *mut _) };
+
+    info_log(&log)
+}
+
+fn program_info_log(program: GLuint) -> String {
+    let mut len = 0;
+    unsafe { gl::GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut len) };
+    if len <= 1 {
+        return String::new();
+    }
+
+    let mut log = vec![0_u8; len as usize];
+    unsafe { gl::GetProgramInfoLog(program, len, std::ptr::null_mut(), log.as_mut_ptr() as *mut _) };
+
+    info_log(&log)
+}
+
+/// Follows every log message that names a line of `source` with that line, numbered like
+/// the compiler counts them, from 1.
+pub fn annotate_log(log: &str, source: &str) -> String {
+    let source: Vec<&str> = source.lines().collect();
+    let mut annotated = String::new();
+
+    for message in log.lines() {
+        annotated.push_str(message);
+        annotated.push('\n');
+
+        let line = log_line_number(message).and_then(|n| Some((n, *source.get(n.checked_sub(1)?)?)));
+        if let Some((n, line)) = line {
+            annotated.push_str(&format!("{:>5} | {}\n", n, line.trim()));
+        }
+    }
+
+    annotated
+}
+
+/// Line number of a message, which drivers start with `<string>:<line>` (Mesa, Mali,
+/// Adreno, ANGLE, possibly after `ERROR: `) or `<string>(<line>)` (NVIDIA).
+fn log_line_number(message: &str) -> Option<usize> {
+    let mut rest = message.trim_start();
+    for prefix in &["ERROR:", "WARNING:", "error:", "warning:"] {
+        if let Some(stripped) = rest.strip_prefix(prefix) {
+            rest = stripped.trim_start();
+            break;
+        }
+    }
+
+    let digits = |s: &str| s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+
+    let string_end = digits(rest);
+    if string_end == 0 {
+        return None;
+    }
+    let rest = &rest[string_end..];
+
+    let (rest, close) = if let Some(rest) = rest.strip_prefix(':') {
+        (rest, None)
+    } else if let Some(rest) = rest.strip_prefix('(') {
+        (rest, Some(')'))
+    } else {
+        return None;
+    };
+    let line_end = digits(rest);
+    if line_end == 0 || close.map_or(false, |c| !rest[line_end..].starts_with(c)) {
+        return None;
+    }
+
+    rest[..line_end].parse().ok()
+}
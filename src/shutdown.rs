@@ -0,0 +1,42 @@
+//! Getting the display back into the state we found it in, whichever way the frame loop ends.
+
+use std::sync::Mutex;
+
+use crate::drm_common::SavedCrtc;
+
+static SAVED_CRTC: Mutex<Option<SavedCrtc>> = Mutex::new(None);
+
+/// Restores the saved CRTC configuration when dropped.
+pub struct RestoreGuard(());
+
+/// Keeps `saved` until the returned guard is dropped or `restore_crtc` is called, whichever
+/// comes first.
+pub fn save_crtc(saved: SavedCrtc) -> RestoreGuard {
+    if let Ok(mut slot) = SAVED_CRTC.lock() {
+        *slot = Some(saved);
+    }
+
+    RestoreGuard(())
+}
+
+/// Restores the configuration kept by `save_crtc` if its guard is still alive, meant for panic
+/// hooks as a panic with `panic = "abort"` never drops the guard.
+pub fn restore_crtc() {
+    // try_lock, the panic might have happened while restoring
+    let saved = match SAVED_CRTC.try_lock() {
+        Ok(mut slot) => slot.take(),
+        Err(_) => None,
+    };
+
+    if let Some(saved) = saved {
+        if let Err(e) = saved.restore() {
+            eprintln!("failed to restore the previous CRTC configuration: {}", e);
+        }
+    }
+}
+
+impl Drop for RestoreGuard {
+    fn drop(&mut self) {
+        restore_crtc();
+    }
+}
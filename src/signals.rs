@@ -0,0 +1,62 @@
+//! Ending the frame loop on signals and frame or time limits, and restoring the display
+//! when the process panics.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use kmscube::shutdown;
+
+use crate::options::Options;
+
+static QUIT: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_signal(_signal: libc::c_int) {
+    QUIT.store(true, Ordering::SeqCst);
+}
+
+/// Makes SIGINT and SIGTERM end the frame loop instead of killing the process, a second signal
+/// kills it as usual. Blocking reads such as waiting for a page flip are restarted rather than
+/// failing with EINTR, the loop then notices on its next frame. Panics restore the saved CRTC
+/// before the default hook runs.
+pub fn install_handlers() {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut());
+    }
+
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        shutdown::restore_crtc();
+        default_hook(info);
+    }));
+}
+
+/// Whether the frame loop should stop.
+pub fn quit_requested() -> bool {
+    QUIT.load(Ordering::SeqCst)
+}
+
+/// When to stop rendering: after `--count` frames, after `--seconds`, or on SIGINT/SIGTERM.
+pub struct FrameLimit {
+    count: Option<u32>,
+    duration: Option<Duration>,
+    start: Instant,
+}
+
+impl FrameLimit {
+    /// The clock for `--seconds` starts here.
+    pub fn new(options: &Options) -> Self {
+        FrameLimit { count: options.count, duration: options.duration, start: Instant::now() }
+    }
+
+    /// Whether the loop should stop before rendering `frame`.
+    pub fn reached(&self, frame: u32) -> bool {
+        quit_requested() ||
+            self.count.map_or(false, |count| frame >= count) ||
+            self.duration.map_or(false, |duration| self.start.elapsed() >= duration)
+    }
+}
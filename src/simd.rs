@@ -0,0 +1,65 @@
+//! `std::arch` kernels behind the `simd` feature: SSE on x86_64, NEON on aarch64 and plain
+//! loops everywhere else.
+//!
+//! The kernels multiply and add in the same order as the scalar code and never fuse, so the
+//! results are bit for bit the same as without the feature.
+
+type Matrix = [[f32; 4]; 4];
+
+/// `v[0] * m[0] + v[1] * m[1] + v[2] * m[2] + v[3] * m[3]`, the rows of `m` weighted by `v`.
+///
+/// That's one row of a product in `ESMatrix`'s storage, and a vector transformed by the GL
+/// matrix.
+#[cfg(target_arch = "x86_64")]
+#[inline]
+pub fn combine(m: &Matrix, v: [f32; 4]) -> [f32; 4] {
+    use std::arch::x86_64::*;
+
+    let mut out = [0.0; 4];
+    // SSE is part of the x86_64 baseline
+    unsafe {
+        let mut acc = _mm_mul_ps(_mm_set1_ps(v[0]), _mm_loadu_ps(m[0].as_ptr()));
+        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(v[1]), _mm_loadu_ps(m[1].as_ptr())));
+        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(v[2]), _mm_loadu_ps(m[2].as_ptr())));
+        acc = _mm_add_ps(acc, _mm_mul_ps(_mm_set1_ps(v[3]), _mm_loadu_ps(m[3].as_ptr())));
+        _mm_storeu_ps(out.as_mut_ptr(), acc);
+    }
+
+    out
+}
+
+#[cfg(target_arch = "aarch64")]
+#[inline]
+pub fn combine(m: &Matrix, v: [f32; 4]) -> [f32; 4] {
+    use std::arch::aarch64::*;
+
+    let mut out = [0.0; 4];
+    // NEON is part of the aarch64 baseline, multiplies and adds stay separate so nothing fuses
+    unsafe {
+        let mut acc = vmulq_n_f32(vld1q_f32(m[0].as_ptr()), v[0]);
+        acc = vaddq_f32(acc, vmulq_n_f32(vld1q_f32(m[1].as_ptr()), v[1]));
+        acc = vaddq_f32(acc, vmulq_n_f32(vld1q_f32(m[2].as_ptr()), v[2]));
+        acc = vaddq_f32(acc, vmulq_n_f32(vld1q_f32(m[3].as_ptr()), v[3]));
+        vst1q_f32(out.as_mut_ptr(), acc);
+    }
+
+    out
+}
+
+/// Portable fallback, laid out so the compiler can vectorize it where it knows how.
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+#[inline]
+pub fn combine(m: &Matrix, v: [f32; 4]) -> [f32; 4] {
+    let mut out = [0.0; 4];
+    for (j, o) in out.iter_mut().enumerate() {
+        *o = v[0] * m[0][j] + v[1] * m[1][j] + v[2] * m[2][j] + v[3] * m[3][j];
+    }
+
+    out
+}
+
+/// The stored product `a b`, same as `ESMatrix::multiply`.
+#[inline]
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    [combine(b, a[0]), combine(b, a[1]), combine(b, a[2]), combine(b, a[3])]
+}
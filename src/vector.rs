@@ -0,0 +1,185 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use crate::es_matrix::ESMatrix;
+#[cfg(feature = "simd")]
+use crate::simd;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Vec4 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Vec3 {
+    pub fn new(x: f32, y: f32, z: f32) -> Vec3 {
+        Vec3 { x, y, z }
+    }
+
+    pub fn dot(self, other: Vec3) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Vec3) -> Vec3 {
+        Vec3 {
+            x: self.y * other.z - self.z * other.y,
+            y: self.z * other.x - self.x * other.z,
+            z: self.x * other.y - self.y * other.x,
+        }
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// Returns `None` for the zero vector, which has no direction.
+    pub fn normalize(self) -> Option<Vec3> {
+        let length = self.length();
+        if length == 0.0 {
+            return None;
+        }
+
+        Some(self * (1.0 / length))
+    }
+
+    pub fn extend(self, w: f32) -> Vec4 {
+        Vec4 { x: self.x, y: self.y, z: self.z, w }
+    }
+}
+
+impl Vec4 {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Vec4 {
+        Vec4 { x, y, z, w }
+    }
+
+    pub fn dot(self, other: Vec4) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    /// Drops `w` without dividing by it.
+    pub fn truncate(self) -> Vec3 {
+        Vec3 { x: self.x, y: self.y, z: self.z }
+    }
+
+    /// The point in 3D space, divided by `w`.
+    pub fn project(self) -> Vec3 {
+        self.truncate() * (1.0 / self.w)
+    }
+}
+
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Self {
+        Vec3 { x: v[0], y: v[1], z: v[2] }
+    }
+}
+
+impl From<Vec3> for [f32; 3] {
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl From<[f32; 4]> for Vec4 {
+    fn from(v: [f32; 4]) -> Self {
+        Vec4 { x: v[0], y: v[1], z: v[2], w: v[3] }
+    }
+}
+
+impl From<Vec4> for [f32; 4] {
+    fn from(v: Vec4) -> Self {
+        [v.x, v.y, v.z, v.w]
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Vec3;
+
+    fn add(self, other: Vec3) -> Vec3 {
+        Vec3 { x: self.x + other.x, y: self.y + other.y, z: self.z + other.z }
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Vec3;
+
+    fn sub(self, other: Vec3) -> Vec3 {
+        Vec3 { x: self.x - other.x, y: self.y - other.y, z: self.z - other.z }
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Vec3;
+
+    fn neg(self) -> Vec3 {
+        Vec3 { x: -self.x, y: -self.y, z: -self.z }
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Vec3;
+
+    fn mul(self, s: f32) -> Vec3 {
+        Vec3 { x: self.x * s, y: self.y * s, z: self.z * s }
+    }
+}
+
+impl Add for Vec4 {
+    type Output = Vec4;
+
+    fn add(self, other: Vec4) -> Vec4 {
+        Vec4 { x: self.x + other.x, y: self.y + other.y, z: self.z + other.z, w: self.w + other.w }
+    }
+}
+
+impl Sub for Vec4 {
+    type Output = Vec4;
+
+    fn sub(self, other: Vec4) -> Vec4 {
+        Vec4 { x: self.x - other.x, y: self.y - other.y, z: self.z - other.z, w: self.w - other.w }
+    }
+}
+
+impl Mul<f32> for Vec4 {
+    type Output = Vec4;
+
+    fn mul(self, s: f32) -> Vec4 {
+        Vec4 { x: self.x * s, y: self.y * s, z: self.z * s, w: self.w * s }
+    }
+}
+
+/// Transforms the vector the way the shaders do with `matrix * vector`.
+impl Mul<Vec4> for ESMatrix {
+    type Output = Vec4;
+
+    fn mul(self, v: Vec4) -> Vec4 {
+        // the rows of the storage are the columns of the GL matrix
+        let m = self.m();
+
+        #[cfg(feature = "simd")]
+        {
+            Vec4::from(simd::combine(&m, v.into()))
+        }
+        #[cfg(not(feature = "simd"))]
+        {
+            let column = |j: usize| v.x * m[0][j] + v.y * m[1][j] + v.z * m[2][j] + v.w * m[3][j];
+            Vec4 { x: column(0), y: column(1), z: column(2), w: column(3) }
+        }
+    }
+}
+
+/// Transforms a point, `w` is taken as 1 and divided out again so projections work as well.
+impl Mul<Vec3> for ESMatrix {
+    type Output = Vec3;
+
+    fn mul(self, v: Vec3) -> Vec3 {
+        (self * v.extend(1.0)).project()
+    }
+}
@@ -0,0 +1,60 @@
+// The IN_FORMATS property blob of a plane, which drm-rs hands out as raw bytes.
+
+use std::io;
+
+/// Header of the IN_FORMATS blob, `struct drm_format_modifier_blob`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct drm_format_modifier_blob {
+    version: u32,
+    flags: u32,
+    count_formats: u32,
+    formats_offset: u32,
+    count_modifiers: u32,
+    modifiers_offset: u32,
+}
+
+/// `struct drm_format_modifier`: `formats` is a mask over the blob's format list, starting
+/// at index `offset`.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct drm_format_modifier {
+    formats: u64,
+    offset: u32,
+    pad: u32,
+    modifier: u64,
+}
+
+fn read_struct<T: Copy>(data: &[u8], offset: usize) -> io::Result<T> {
+    let end = offset.checked_add(std::mem::size_of::<T>());
+    if end.map_or(true, |end| end > data.len()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "truncated IN_FORMATS blob"));
+    }
+
+    Ok(unsafe { std::ptr::read_unaligned(data[offset..].as_ptr() as *const T) })
+}
+
+/// Parses the IN_FORMATS blob of a plane into the modifiers it supports for `format`.
+pub fn parse_in_formats(data: &[u8], format: u32) -> io::Result<Vec<u64>> {
+    let header: drm_format_modifier_blob = read_struct(data, 0)?;
+
+    let formats = (0..header.count_formats as usize)
+        .map(|i| read_struct::<u32>(data, header.formats_offset as usize + i * 4))
+        .collect::<io::Result<Vec<u32>>>()?;
+    let index = match formats.iter().position(|&f| f == format) {
+        Some(index) => index as u32,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut modifiers = Vec::new();
+    for i in 0..header.count_modifiers as usize {
+        let offset = header.modifiers_offset as usize + i * std::mem::size_of::<drm_format_modifier>();
+        let entry: drm_format_modifier = read_struct(data, offset)?;
+
+        if index >= entry.offset && index - entry.offset < 64 && entry.formats & (1 << (index - entry.offset)) != 0 {
+            modifiers.push(entry.modifier);
+        }
+    }
+
+    Ok(modifiers)
+}
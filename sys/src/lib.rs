@@ -1,10 +1,12 @@
 extern crate drm;
 extern crate gbm;
 
+pub mod kms;
+
 use drm::control::Device as ControlDevice;
 
 use std::fs::{File, OpenOptions};
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::io::{AsFd, BorrowedFd};
 
 use std::os::raw::{c_void, c_char, c_int};
 
@@ -44,10 +46,10 @@ pub mod gles2 {
 // This is our customized struct that implements the traits in drm.
 pub struct Card(File);
 
-// Need to implement AsRawFd before we can implement drm::Device
-impl AsRawFd for Card {
-    fn as_raw_fd(&self) -> RawFd {
-        self.0.as_raw_fd()
+// Need to implement AsFd before we can implement drm::Device
+impl AsFd for Card {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
     }
 }
 
@@ -55,14 +57,18 @@ impl drm::Device for Card {}
 impl ControlDevice for Card {}
 
 impl Card {
-    pub fn open(path: &str) -> Self {
+    pub fn open(path: &str) -> std::io::Result<Self> {
         let mut options = OpenOptions::new();
         options.read(true);
         options.write(true);
-        Card(options.open(path).unwrap())
+        Ok(Card(options.open(path)?))
+    }
+
+    pub fn try_clone(&self) -> std::io::Result<Self> {
+        Ok(Card(self.0.try_clone()?))
     }
 
-    pub fn open_global() -> Self {
+    pub fn open_global() -> std::io::Result<Self> {
         Self::open("/dev/dri/card0")
     }
 
@@ -0,0 +1,33 @@
+//! Tolerances and assertions shared by the matrix, vector and quaternion tests.
+
+// every test crate compiles its own copy and none uses all of it
+#![allow(dead_code)]
+
+use kmscube::es_matrix::ESMatrix;
+use kmscube::vector::Vec3;
+
+pub const EPSILON: f32 = 1e-5;
+
+/// Compares to `expected`, given in storage order, within `EPSILON`.
+pub fn assert_matrix_eq(actual: ESMatrix, expected: [[f32; 4]; 4]) {
+    let actual = actual.m();
+    let close = actual.iter().flatten().zip(expected.iter().flatten()).all(|(a, e)| (a - e).abs() <= EPSILON);
+    assert!(close, "expected {:?}\n     got {:?}", expected, actual);
+}
+
+/// Compares within `tolerance`, relative for elements larger than 1. `context` says which
+/// check failed.
+pub fn assert_matrix_close(actual: ESMatrix, expected: ESMatrix, tolerance: f32, context: &str) {
+    let close = actual.l().iter().zip(expected.l().iter())
+        .all(|(a, e)| (a - e).abs() <= tolerance * e.abs().max(1.0));
+    assert!(close, "{}\nexpected {:?}\n     got {:?}", context, expected, actual);
+}
+
+pub fn assert_vec3_eq(actual: Vec3, expected: Vec3) {
+    assert!((actual - expected).length() <= EPSILON, "expected {:?}, got {:?}", expected, actual);
+}
+
+pub fn assert_point_eq(actual: [f32; 3], expected: [f32; 3]) {
+    let close = actual.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() <= EPSILON);
+    assert!(close, "expected {:?}, got {:?}", expected, actual);
+}
@@ -0,0 +1,19 @@
+//! The format table behind `--format`, checked against the fourcc codes the kernel uses.
+
+use kmscube::config::{format_name, FORMATS};
+
+#[test]
+fn format_names_are_their_fourcc_codes() {
+    for &(name, format) in FORMATS {
+        let mut code = [0_u8; 4];
+        code.copy_from_slice(name.as_bytes());
+        assert_eq!(format as u32, u32::from_le_bytes(code), "{}", name);
+    }
+}
+
+#[test]
+fn half_float_format_is_available() {
+    let &(_, format) = FORMATS.iter().find(|&&(name, _)| name == "XB4H").unwrap();
+    assert_eq!(format as u32, 0x4834_4258);
+    assert_eq!(format_name(format), "XB4H");
+}
@@ -0,0 +1,203 @@
+//! Checks the `ESMatrix` builders against matrices worked out by hand.
+//!
+//! The matrices are stored transposed compared to the usual notation, so the translation
+//! lives in the last row and points transform as row vectors.
+
+mod common;
+
+use kmscube::es_matrix::ESMatrix;
+
+use common::{assert_matrix_eq, assert_point_eq, EPSILON};
+
+/// Transforms the point `p`, divided by w.
+fn transform(m: ESMatrix, p: [f32; 3]) -> [f32; 3] {
+    let m = m.m();
+    let v = [p[0], p[1], p[2], 1.0];
+    let out: Vec<f32> = (0..4).map(|j| (0..4).map(|i| v[i] * m[i][j]).sum()).collect();
+
+    [out[0] / out[3], out[1] / out[3], out[2] / out[3]]
+}
+
+#[test]
+fn scale_multiplies_the_axes() {
+    assert_matrix_eq(ESMatrix::identity().scale(2.0, 3.0, 4.0), [
+        [2.0, 0.0, 0.0, 0.0],
+        [0.0, 3.0, 0.0, 0.0],
+        [0.0, 0.0, 4.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+}
+
+#[test]
+fn scale_applies_before_translate() {
+    let m = ESMatrix::identity().translate(1.0, 2.0, 3.0).scale(2.0, 2.0, 2.0);
+    assert_point_eq(transform(m, [1.0, 1.0, 1.0]), [3.0, 4.0, 5.0]);
+}
+
+#[test]
+fn perspective_matches_the_frustum() {
+    // tan(45°) = 1, so the near plane spans [-2, 2] vertically and [-4, 4] horizontally
+    let perspective = ESMatrix::identity().perspective(90.0, 2.0, 2.0, 10.0);
+    let frustum = ESMatrix::identity().frustum(-4.0, 4.0, -2.0, 2.0, 2.0, 10.0);
+
+    assert_matrix_eq(perspective, frustum.m());
+    assert_matrix_eq(perspective, [
+        [0.5, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, -1.5, -1.0],
+        [0.0, 0.0, -5.0, 0.0],
+    ]);
+}
+
+#[test]
+fn perspective_maps_near_and_far_to_the_depth_range() {
+    let m = ESMatrix::identity().perspective(60.0, 1.5, 1.0, 100.0);
+
+    assert_point_eq(transform(m, [0.0, 0.0, -1.0]), [0.0, 0.0, -1.0]);
+    assert_point_eq(transform(m, [0.0, 0.0, -100.0]), [0.0, 0.0, 1.0]);
+}
+
+#[test]
+fn ortho_maps_the_box_to_clip_space() {
+    let m = ESMatrix::identity().ortho(0.0, 640.0, 0.0, 480.0, -1.0, 1.0);
+
+    assert_matrix_eq(m, [
+        [2.0 / 640.0, 0.0, 0.0, 0.0],
+        [0.0, 2.0 / 480.0, 0.0, 0.0],
+        [0.0, 0.0, -1.0, 0.0],
+        [-1.0, -1.0, 0.0, 1.0],
+    ]);
+    assert_point_eq(transform(m, [640.0, 480.0, -1.0]), [1.0, 1.0, 1.0]);
+}
+
+#[test]
+fn ortho_ignores_an_empty_box() {
+    let m = ESMatrix::identity().ortho(1.0, 1.0, 0.0, 1.0, 0.0, 1.0);
+    assert_matrix_eq(m, ESMatrix::identity().m());
+}
+
+#[test]
+fn look_at_along_the_default_view_is_a_translation() {
+    let m = ESMatrix::identity().look_at([0.0, 0.0, 5.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+    assert_matrix_eq(m, ESMatrix::identity().translate(0.0, 0.0, -5.0).m());
+}
+
+#[test]
+fn look_at_from_the_side() {
+    let m = ESMatrix::identity().look_at([5.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]);
+
+    assert_matrix_eq(m, [
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [-1.0, 0.0, 0.0, 0.0],
+        [0.0, 0.0, -5.0, 1.0],
+    ]);
+    // the target ends up straight ahead, +z of the world to the left
+    assert_point_eq(transform(m, [0.0, 0.0, 0.0]), [0.0, 0.0, -5.0]);
+    assert_point_eq(transform(m, [0.0, 0.0, 1.0]), [-1.0, 0.0, -5.0]);
+}
+
+#[test]
+fn look_at_ignores_degenerate_cameras() {
+    let identity = ESMatrix::identity().m();
+
+    assert_matrix_eq(ESMatrix::identity().look_at([1.0, 2.0, 3.0], [1.0, 2.0, 3.0], [0.0, 1.0, 0.0]), identity);
+    assert_matrix_eq(ESMatrix::identity().look_at([0.0, 5.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]), identity);
+}
+
+#[test]
+fn transpose_swaps_rows_and_columns() {
+    let m = ESMatrix::identity().translate(1.0, 2.0, 3.0);
+
+    assert_matrix_eq(m.transpose(), [
+        [1.0, 0.0, 0.0, 1.0],
+        [0.0, 1.0, 0.0, 2.0],
+        [0.0, 0.0, 1.0, 3.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ]);
+    assert_matrix_eq(m.transpose().transpose(), m.m());
+}
+
+#[test]
+fn determinant_of_known_matrices() {
+    assert!((ESMatrix::identity().determinant() - 1.0).abs() <= EPSILON);
+    assert!((ESMatrix::identity().scale(2.0, 3.0, 4.0).determinant() - 24.0).abs() <= EPSILON);
+    // rotations and translations keep volumes
+    let rigid = ESMatrix::identity().translate(4.0, -2.0, 7.0).rotate(33.0, 1.0, 2.0, 3.0);
+    assert!((rigid.determinant() - 1.0).abs() <= EPSILON);
+    assert_eq!(ESMatrix::identity().scale(1.0, 0.0, 1.0).determinant(), 0.0);
+}
+
+#[test]
+fn inverse_undoes_the_transform() {
+    let m = ESMatrix::identity()
+        .translate(1.0, -2.0, 3.0)
+        .rotate(40.0, 0.0, 1.0, 1.0)
+        .scale(2.0, 0.5, 3.0);
+    let inverse = m.inverse().expect("matrix is invertible");
+
+    assert_matrix_eq(ESMatrix::multiply(m, inverse), ESMatrix::identity().m());
+    assert_matrix_eq(ESMatrix::multiply(inverse, m), ESMatrix::identity().m());
+    assert_point_eq(transform(inverse, transform(m, [0.3, 0.7, -1.1])), [0.3, 0.7, -1.1]);
+}
+
+#[test]
+fn inverse_of_a_projection() {
+    let m = ESMatrix::identity().perspective(90.0, 2.0, 1.0, 4.0);
+    let inverse = m.inverse().expect("projection is invertible");
+
+    assert_point_eq(transform(inverse, [0.0, 0.0, -1.0]), [0.0, 0.0, -1.0]);
+    assert_point_eq(transform(inverse, [1.0, 1.0, 1.0]), [8.0, 4.0, -4.0]);
+}
+
+#[test]
+fn singular_matrices_have_no_inverse() {
+    assert!(ESMatrix::identity().scale(1.0, 0.0, 1.0).inverse().is_none());
+    assert!(ESMatrix::default().inverse().is_none());
+}
+
+#[test]
+fn normal_matrix_of_a_rotation_is_the_rotation() {
+    let m = ESMatrix::identity().translate(0.0, 0.0, -8.0).rotate(30.0, 1.0, 1.0, 0.0);
+    let upper = m.m();
+
+    let normal = m.normal_matrix();
+    for i in 0..3 {
+        for j in 0..3 {
+            assert!((normal[i * 3 + j] - upper[i][j]).abs() <= EPSILON, "{:?}", normal);
+        }
+    }
+}
+
+#[test]
+fn normal_matrix_inverts_non_uniform_scale() {
+    let normal = ESMatrix::identity().translate(5.0, 0.0, 0.0).scale(2.0, 4.0, 0.5).normal_matrix();
+    let expected = [
+        0.5, 0.0, 0.0,
+        0.0, 0.25, 0.0,
+        0.0, 0.0, 2.0,
+    ];
+
+    assert!(normal.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() <= EPSILON), "{:?}", normal);
+}
+
+#[test]
+fn normal_matrix_keeps_normals_perpendicular() {
+    let m = ESMatrix::identity().rotate(20.0, 0.0, 0.0, 1.0).scale(3.0, 1.0, 1.0);
+    let normal = m.normal_matrix();
+
+    // the plane x + y = 0 with its normal, transformed as row vectors like the points
+    let tangent = [1.0, -1.0, 0.0];
+    let n = [1.0, 1.0, 0.0];
+
+    let upper = m.m();
+    let t: Vec<f32> = (0..3).map(|j| (0..3).map(|i| tangent[i] * upper[i][j]).sum()).collect();
+    let n: Vec<f32> = (0..3).map(|j| (0..3).map(|i| n[i] * normal[i * 3 + j]).sum()).collect();
+    let dot: f32 = t.iter().zip(n.iter()).map(|(a, b)| a * b).sum();
+    assert!(dot.abs() <= EPSILON, "normal {:?} not perpendicular to {:?}", n, t);
+
+    // the plain 3x3 doesn't manage that
+    let plain: Vec<f32> = (0..3).map(|j| (0..3).map(|i| [1.0, 1.0, 0.0][i] * upper[i][j]).sum()).collect();
+    let dot: f32 = t.iter().zip(plain.iter()).map(|(a, b)| a * b).sum();
+    assert!(dot.abs() > 0.1);
+}
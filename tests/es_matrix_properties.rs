@@ -0,0 +1,327 @@
+//! Checks `ESMatrix` over randomized inputs, both for identities that have to hold and
+//! against a separate reference written in the usual column-vector notation.
+//!
+//! The reference follows the OpenGL man pages in f64: matrices are row-major in the maths
+//! sense and the builders multiply on the right like `glTranslate`, `glRotate` and
+//! `glFrustum` do. An `ESMatrix` stores the transpose of that, and its `rotate` turns the
+//! other way like `esRotate`.
+
+mod common;
+
+use kmscube::es_matrix::ESMatrix;
+use kmscube::vector::Vec4;
+
+use common::assert_matrix_close;
+
+const ROUNDS: usize = 1000;
+
+type Mat = [[f64; 4]; 4];
+
+/// xorshift64*, fixed seeds keep failures reproducible.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Uniform in `[low, high)`.
+    fn range(&mut self, low: f32, high: f32) -> f32 {
+        let unit = (self.next() >> 40) as f32 / (1u64 << 24) as f32;
+        low + unit * (high - low)
+    }
+
+    fn axis(&mut self) -> [f32; 3] {
+        loop {
+            let axis = [self.range(-1.0, 1.0), self.range(-1.0, 1.0), self.range(-1.0, 1.0)];
+            if axis.iter().map(|v| v * v).sum::<f32>() > 1e-2 {
+                return axis;
+            }
+        }
+    }
+
+    fn matrix(&mut self) -> ESMatrix {
+        let mut m = [[0.0; 4]; 4];
+        m.iter_mut().flatten().for_each(|v| *v = self.range(-4.0, 4.0));
+        ESMatrix::new(m)
+    }
+}
+
+fn identity() -> Mat {
+    let mut m = [[0.0; 4]; 4];
+    (0..4).for_each(|i| m[i][i] = 1.0);
+    m
+}
+
+fn mat_mul(a: &Mat, b: &Mat) -> Mat {
+    let mut result = [[0.0; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            result[i][j] = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    result
+}
+
+fn gl_translate(m: &Mat, t: [f32; 3]) -> Mat {
+    let mut translation = identity();
+    (0..3).for_each(|i| translation[i][3] = t[i] as f64);
+    mat_mul(m, &translation)
+}
+
+fn gl_rotate(m: &Mat, angle: f32, axis: [f32; 3]) -> Mat {
+    let length = axis.iter().map(|&v| (v as f64).powi(2)).sum::<f64>().sqrt();
+    let (x, y, z) = (axis[0] as f64 / length, axis[1] as f64 / length, axis[2] as f64 / length);
+    let (s, c) = (angle as f64).to_radians().sin_cos();
+    let rotation = [
+        [x * x * (1.0 - c) + c, x * y * (1.0 - c) - z * s, x * z * (1.0 - c) + y * s, 0.0],
+        [y * x * (1.0 - c) + z * s, y * y * (1.0 - c) + c, y * z * (1.0 - c) - x * s, 0.0],
+        [x * z * (1.0 - c) - y * s, y * z * (1.0 - c) + x * s, z * z * (1.0 - c) + c, 0.0],
+        [0.0, 0.0, 0.0, 1.0],
+    ];
+    mat_mul(m, &rotation)
+}
+
+fn gl_frustum(m: &Mat, planes: [f32; 6]) -> Mat {
+    let [l, r, b, t, n, f] = [
+        planes[0] as f64, planes[1] as f64, planes[2] as f64,
+        planes[3] as f64, planes[4] as f64, planes[5] as f64,
+    ];
+    let frustum = [
+        [2.0 * n / (r - l), 0.0, (r + l) / (r - l), 0.0],
+        [0.0, 2.0 * n / (t - b), (t + b) / (t - b), 0.0],
+        [0.0, 0.0, -(f + n) / (f - n), -2.0 * f * n / (f - n)],
+        [0.0, 0.0, -1.0, 0.0],
+    ];
+    mat_mul(m, &frustum)
+}
+
+/// The reference matrix in `ESMatrix`'s transposed storage.
+fn to_es(m: &Mat) -> ESMatrix {
+    let mut result = [[0.0; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            result[j][i] = m[i][j] as f32;
+        }
+    }
+    ESMatrix::new(result)
+}
+
+fn from_es(m: ESMatrix) -> Mat {
+    let m = m.m();
+    let mut result = [[0.0; 4]; 4];
+    for i in 0..4 {
+        for j in 0..4 {
+            result[j][i] = m[i][j] as f64;
+        }
+    }
+    result
+}
+
+/// Transforms the point with the usual column-vector maths and divides by w.
+fn project(m: ESMatrix, p: [f32; 3]) -> [f32; 3] {
+    let m = from_es(m);
+    let v = [p[0] as f64, p[1] as f64, p[2] as f64, 1.0];
+    let out: Vec<f64> = (0..4).map(|i| (0..4).map(|k| m[i][k] * v[k]).sum()).collect();
+
+    [(out[0] / out[3]) as f32, (out[1] / out[3]) as f32, (out[2] / out[3]) as f32]
+}
+
+fn frustum_planes(rng: &mut Rng) -> [f32; 6] {
+    let near = rng.range(0.1, 5.0);
+    [
+        rng.range(-5.0, -0.1), rng.range(0.1, 5.0),
+        rng.range(-5.0, -0.1), rng.range(0.1, 5.0),
+        near, near + rng.range(0.5, 50.0),
+    ]
+}
+
+#[test]
+fn rotating_a_full_turn_is_the_identity() {
+    let mut rng = Rng::new(1);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+        let [x, y, z] = rng.axis();
+
+        assert_matrix_close(m.rotate(360.0, x, y, z), m, 1e-5, "rotate by 360");
+        assert_matrix_close(m.rotate(-360.0, x, y, z), m, 1e-5, "rotate by -360");
+    }
+}
+
+#[test]
+fn rotating_back_undoes_the_rotation() {
+    let mut rng = Rng::new(2);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+        let angle = rng.range(-720.0, 720.0);
+        let [x, y, z] = rng.axis();
+
+        assert_matrix_close(m.rotate(angle, x, y, z).rotate(-angle, x, y, z), m, 1e-5, "rotate there and back");
+    }
+}
+
+#[test]
+fn multiply_is_associative() {
+    let mut rng = Rng::new(3);
+    for _ in 0..ROUNDS {
+        let (a, b, c) = (rng.matrix(), rng.matrix(), rng.matrix());
+
+        let left = ESMatrix::multiply(ESMatrix::multiply(a, b), c);
+        let right = ESMatrix::multiply(a, ESMatrix::multiply(b, c));
+        assert_matrix_close(left, right, 1e-4, "(a b) c = a (b c)");
+    }
+}
+
+#[test]
+fn multiply_by_the_identity_changes_nothing() {
+    let mut rng = Rng::new(4);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+
+        assert_eq!(ESMatrix::multiply(m, ESMatrix::identity()).m(), m.m());
+        assert_eq!(ESMatrix::multiply(ESMatrix::identity(), m).m(), m.m());
+    }
+}
+
+#[test]
+fn frustum_maps_near_and_far_planes_to_the_depth_range() {
+    let mut rng = Rng::new(5);
+    for _ in 0..ROUNDS {
+        let planes = frustum_planes(&mut rng);
+        let [left, right, bottom, top, near, far] = planes;
+        let m = ESMatrix::identity().frustum(left, right, bottom, top, near, far);
+
+        // corners of the near plane go to the corners of clip space
+        for &(x, y, ndc_x, ndc_y) in &[(left, bottom, -1.0, -1.0), (right, top, 1.0, 1.0)] {
+            let p = project(m, [x, y, -near]);
+            let expected = [ndc_x, ndc_y, -1.0];
+            assert!(p.iter().zip(expected.iter()).all(|(a, e)| (a - e).abs() <= 1e-4), "{:?}: near {:?}", planes, p);
+        }
+
+        // anywhere on the far plane ends up at depth 1
+        let scale = far / near;
+        let x = rng.range(left, right) * scale;
+        let y = rng.range(bottom, top) * scale;
+        let p = project(m, [x, y, -far]);
+        assert!((p[2] - 1.0).abs() <= 1e-3, "{:?}: far {:?}", planes, p);
+    }
+}
+
+#[test]
+fn translate_matches_the_reference() {
+    let mut rng = Rng::new(6);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+        let t = [rng.range(-10.0, 10.0), rng.range(-10.0, 10.0), rng.range(-10.0, 10.0)];
+
+        let expected = to_es(&gl_translate(&from_es(m), t));
+        assert_matrix_close(m.translate(t[0], t[1], t[2]), expected, 1e-5, "translate");
+    }
+}
+
+#[test]
+fn rotate_matches_the_reference_turned_the_other_way() {
+    let mut rng = Rng::new(7);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+        let angle = rng.range(-360.0, 360.0);
+        let axis = rng.axis();
+
+        let expected = to_es(&gl_rotate(&from_es(m), -angle, axis));
+        assert_matrix_close(m.rotate(angle, axis[0], axis[1], axis[2]), expected, 1e-5, "rotate");
+    }
+}
+
+#[test]
+fn frustum_matches_the_reference() {
+    let mut rng = Rng::new(8);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+        let planes = frustum_planes(&mut rng);
+        let [left, right, bottom, top, near, far] = planes;
+
+        let expected = to_es(&gl_frustum(&from_es(m), planes));
+        assert_matrix_close(m.frustum(left, right, bottom, top, near, far), expected, 1e-4, "frustum");
+    }
+}
+
+#[test]
+fn multiply_matches_the_reference() {
+    let mut rng = Rng::new(9);
+    for _ in 0..ROUNDS {
+        let (a, b) = (rng.matrix(), rng.matrix());
+
+        // the stored product a b is the transpose of B A
+        let expected = to_es(&mat_mul(&from_es(b), &from_es(a)));
+        assert_matrix_close(ESMatrix::multiply(a, b), expected, 1e-5, "multiply");
+    }
+}
+
+#[test]
+fn chains_of_transforms_match_the_reference() {
+    let mut rng = Rng::new(10);
+    for _ in 0..ROUNDS {
+        let mut actual = ESMatrix::identity();
+        let mut expected = identity();
+
+        for _ in 0..6 {
+            match rng.next() % 3 {
+                0 => {
+                    let t = [rng.range(-10.0, 10.0), rng.range(-10.0, 10.0), rng.range(-10.0, 10.0)];
+                    actual = actual.translate(t[0], t[1], t[2]);
+                    expected = gl_translate(&expected, t);
+                }
+                1 => {
+                    let angle = rng.range(-360.0, 360.0);
+                    let axis = rng.axis();
+                    actual = actual.rotate(angle, axis[0], axis[1], axis[2]);
+                    expected = gl_rotate(&expected, -angle, axis);
+                }
+                _ => {
+                    let planes = frustum_planes(&mut rng);
+                    let [left, right, bottom, top, near, far] = planes;
+                    actual = actual.frustum(left, right, bottom, top, near, far);
+                    expected = gl_frustum(&expected, planes);
+                }
+            }
+        }
+
+        // a couple of frustums in a row grow the entries quickly, compare relative to the largest
+        let largest = expected.iter().flatten().fold(1.0_f64, |m, v| m.max(v.abs())) as f32;
+        let close = actual.l().iter().zip(to_es(&expected).l().iter())
+            .all(|(a, e)| (a - e).abs() <= 1e-4 * largest);
+        assert!(close, "expected {:?}\n     got {:?}", to_es(&expected), actual);
+    }
+}
+
+#[test]
+fn multiply_matches_the_scalar_version_exactly() {
+    // with the simd feature the kernels have to round the same way as the scalar code
+    let mut rng = Rng::new(11);
+    for _ in 0..ROUNDS {
+        let (a, b) = (rng.matrix(), rng.matrix());
+
+        assert_eq!(ESMatrix::multiply(a, b).m(), ESMatrix::multiply_scalar(a, b).m());
+    }
+}
+
+#[test]
+fn transforming_a_vector_matches_the_scalar_version_exactly() {
+    let mut rng = Rng::new(12);
+    for _ in 0..ROUNDS {
+        let m = rng.matrix();
+        let v = [rng.range(-4.0, 4.0), rng.range(-4.0, 4.0), rng.range(-4.0, 4.0), rng.range(-4.0, 4.0)];
+
+        // a vector is a matrix with a single row in this storage
+        let row = ESMatrix::new([v, [0.0; 4], [0.0; 4], [0.0; 4]]);
+        let expected = ESMatrix::multiply_scalar(row, m).m()[0];
+        assert_eq!(<[f32; 4]>::from(m * Vec4::from(v)), expected);
+    }
+}
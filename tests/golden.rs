@@ -0,0 +1,126 @@
+//! Renders the smooth shaded cube offscreen and compares selected frames with the reference
+//! images in `tests/golden`.
+//!
+//! Needs an EGL implementation with EGL_MESA_platform_surfaceless, the test forces Mesa's
+//! software rasterizer so the results don't depend on the GPU. Run with `KMSCUBE_BLESS=1` to
+//! regenerate the references after an intended change in the output.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const FRAMES: &[u32] = &[0, 90, 360];
+const SIZE: (u32, u32) = (320, 240);
+
+/// Largest per channel difference still counted as a match.
+const TOLERANCE: u8 = 8;
+/// Share of pixels allowed to exceed the tolerance, rasterizers may disagree along edges.
+const MAX_MISMATCHED: f64 = 0.002;
+
+struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+fn load_png(path: &Path) -> Image {
+    let decoder = png::Decoder::new(File::open(path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e)));
+    let (info, mut reader) = decoder.read_info().unwrap();
+    assert_eq!(info.color_type, png::ColorType::RGBA, "{}: expected RGBA", path.display());
+    assert_eq!(info.bit_depth, png::BitDepth::Eight, "{}: expected 8 bit", path.display());
+
+    let mut pixels = vec![0; info.buffer_size()];
+    reader.next_frame(&mut pixels).unwrap();
+
+    Image { width: info.width, height: info.height, pixels }
+}
+
+fn save_png(path: &Path, image: &Image) {
+    let mut encoder = png::Encoder::new(File::create(path).unwrap(), image.width, image.height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&image.pixels).unwrap();
+}
+
+/// Returns the number of mismatched pixels and an image with those marked red.
+fn compare(expected: &Image, actual: &Image) -> (usize, Image) {
+    let mut mismatched = 0;
+    let mut diff = Vec::with_capacity(expected.pixels.len());
+
+    for (e, a) in expected.pixels.chunks(4).zip(actual.pixels.chunks(4)) {
+        let matches = e.iter().zip(a.iter()).all(|(&e, &a)| (e as i16 - a as i16).abs() <= TOLERANCE as i16);
+        if matches {
+            // faded reference for orientation
+            let luma = ((e[0] as u32 + e[1] as u32 + e[2] as u32) / 3 / 4) as u8;
+            diff.extend_from_slice(&[luma, luma, luma, 0xff]);
+        } else {
+            mismatched += 1;
+            diff.extend_from_slice(&[0xff, 0, 0, 0xff]);
+        }
+    }
+
+    (mismatched, Image { width: expected.width, height: expected.height, pixels: diff })
+}
+
+fn render(out_dir: &Path) {
+    let frames: Vec<String> = FRAMES.iter().map(|f| f.to_string()).collect();
+    let count = FRAMES.iter().max().unwrap() + 1;
+
+    let output = Command::new(env!("CARGO_BIN_EXE_kmscube-rs"))
+        .arg("--surfaceless")
+        .arg(format!("--vmode={}x{}", SIZE.0, SIZE.1))
+        .arg(format!("--count={}", count))
+        .arg(format!("--capture={}", frames.join(",")))
+        .arg(format!("--capture-dir={}", out_dir.display()))
+        .env("LIBGL_ALWAYS_SOFTWARE", "1")
+        .env("GALLIUM_DRIVER", "llvmpipe")
+        .output()
+        .expect("failed to run kmscube-rs");
+
+    assert!(output.status.success(), "kmscube-rs failed:\n{}\n{}",
+        String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+}
+
+#[test]
+fn cube_smooth_matches_golden_images() {
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let out_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let bless = std::env::var_os("KMSCUBE_BLESS").is_some();
+
+    let _ = std::fs::remove_dir_all(&out_dir);
+    std::fs::create_dir_all(&out_dir).unwrap();
+
+    render(&out_dir);
+
+    let mut failures = Vec::new();
+
+    for frame in FRAMES {
+        let actual_path = out_dir.join(format!("frame-{:05}.png", frame));
+        let golden_path = golden_dir.join(format!("cube_smooth-{:05}.png", frame));
+
+        if bless {
+            std::fs::create_dir_all(&golden_dir).unwrap();
+            std::fs::copy(&actual_path, &golden_path).unwrap();
+            continue;
+        }
+
+        assert!(golden_path.exists(), "missing reference {}, run with KMSCUBE_BLESS=1 to create it",
+            golden_path.display());
+
+        let expected = load_png(&golden_path);
+        let actual = load_png(&actual_path);
+        assert_eq!((actual.width, actual.height), (expected.width, expected.height),
+            "frame {} has the wrong size", frame);
+
+        let (mismatched, diff) = compare(&expected, &actual);
+        let ratio = mismatched as f64 / (expected.width * expected.height) as f64;
+        if ratio > MAX_MISMATCHED {
+            let diff_path = out_dir.join(format!("diff-{:05}.png", frame));
+            save_png(&diff_path, &diff);
+            failures.push(format!("frame {}: {} pixels differ ({:.3}%), see {}",
+                frame, mismatched, ratio * 100.0, diff_path.display()));
+        }
+    }
+
+    assert!(failures.is_empty(), "golden image mismatch:\n{}", failures.join("\n"));
+}
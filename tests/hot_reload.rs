@@ -0,0 +1,84 @@
+//! Loading shader files and noticing when they change, without a GL context.
+
+use std::fs;
+use std::path::PathBuf;
+
+use kmscube::hot_reload::ShaderFiles;
+
+/// Fresh scratch directory for one test.
+fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kmscube-hot-reload-{}-{}", std::process::id(), test));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn files_replace_the_built_in_sources() {
+    let dir = scratch_dir("load");
+    let fragment = dir.join("cube.frag");
+    fs::write(&fragment, "from file").unwrap();
+
+    let files = ShaderFiles::new(None, Some(fragment));
+    let (vertex, fragment) = files.load("built in vertex", "built in fragment").unwrap();
+    assert_eq!(vertex, "built in vertex");
+    assert_eq!(fragment, "from file");
+
+    let missing = ShaderFiles::new(Some(dir.join("missing.vert")), None);
+    assert!(missing.load("", "").is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writing_a_file_is_noticed_once() {
+    let dir = scratch_dir("write");
+    let vertex = dir.join("cube.vert");
+    fs::write(&vertex, "first").unwrap();
+
+    let mut files = ShaderFiles::new(Some(vertex.clone()), None);
+    assert!(!files.changed(), "nothing is watched before watch()");
+    files.watch().unwrap();
+    assert!(!files.changed());
+
+    fs::write(&vertex, "second").unwrap();
+    assert!(files.changed());
+    assert!(!files.changed());
+    assert_eq!(files.load("", "").unwrap().0, "second");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn replacing_a_file_is_noticed() {
+    // how most editors save
+    let dir = scratch_dir("rename");
+    let fragment = dir.join("cube.frag");
+    fs::write(&fragment, "first").unwrap();
+
+    let mut files = ShaderFiles::new(None, Some(fragment.clone()));
+    files.watch().unwrap();
+
+    let temporary = dir.join(".cube.frag.swp");
+    fs::write(&temporary, "second").unwrap();
+    fs::rename(&temporary, &fragment).unwrap();
+    assert!(files.changed());
+    assert_eq!(files.load("", "").unwrap().1, "second");
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn other_files_in_the_directory_are_ignored() {
+    let dir = scratch_dir("other");
+    let vertex = dir.join("cube.vert");
+    fs::write(&vertex, "").unwrap();
+
+    let mut files = ShaderFiles::new(Some(vertex), None);
+    files.watch().unwrap();
+
+    fs::write(dir.join("notes.txt"), "unrelated").unwrap();
+    assert!(!files.changed());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
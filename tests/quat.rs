@@ -0,0 +1,108 @@
+//! Quaternion rotations, composition and interpolation.
+
+mod common;
+
+use kmscube::es_matrix::ESMatrix;
+use kmscube::quat::Quat;
+use kmscube::vector::Vec3;
+
+use common::{assert_matrix_eq, assert_vec3_eq, EPSILON};
+
+fn z_axis() -> Vec3 {
+    Vec3::new(0.0, 0.0, 1.0)
+}
+
+#[test]
+fn rotates_counter_clockwise_around_the_axis() {
+    let q = Quat::from_axis_angle(z_axis(), 90.0);
+
+    assert_vec3_eq(q * Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+    assert_vec3_eq(q * Vec3::new(0.0, 1.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+    assert_vec3_eq(q * z_axis(), z_axis());
+}
+
+#[test]
+fn zero_axis_is_the_identity() {
+    assert_eq!(Quat::from_axis_angle(Vec3::default(), 45.0), Quat::identity());
+}
+
+#[test]
+fn matrix_rotates_like_the_quaternion() {
+    let q = Quat::from_axis_angle(Vec3::new(1.0, 2.0, -0.5), 73.0);
+    let m = q.to_matrix();
+
+    for &v in &[Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.3, -2.0, 5.0), Vec3::new(-1.0, 1.0, 1.0)] {
+        assert_vec3_eq(m * v, q * v);
+    }
+}
+
+#[test]
+fn matrix_is_esmatrix_rotate_turned_the_other_way() {
+    // ESMatrix::rotate keeps esRotate's clockwise sense
+    let axis = Vec3::new(0.0, 1.0, 1.0);
+    let expected = ESMatrix::identity().rotate(-30.0, axis.x, axis.y, axis.z);
+
+    assert_matrix_eq(Quat::from_axis_angle(axis, 30.0).to_matrix(), expected.m());
+}
+
+#[test]
+fn product_applies_the_right_operand_first() {
+    let a = Quat::from_axis_angle(z_axis(), 90.0);
+    let b = Quat::from_axis_angle(Vec3::new(1.0, 0.0, 0.0), 90.0);
+    let v = Vec3::new(0.0, 1.0, 0.0);
+
+    assert_vec3_eq((a * b) * v, a * (b * v));
+    assert_matrix_eq((a * b).to_matrix(), (a.to_matrix() * b.to_matrix()).m());
+}
+
+#[test]
+fn conjugate_undoes_the_rotation() {
+    let q = Quat::from_axis_angle(Vec3::new(2.0, -1.0, 0.5), 123.0);
+    let v = Vec3::new(0.4, 0.5, 0.6);
+
+    assert_vec3_eq(q.conjugate() * (q * v), v);
+}
+
+#[test]
+fn slerp_hits_both_ends_and_the_middle() {
+    let a = Quat::from_axis_angle(z_axis(), 10.0);
+    let b = Quat::from_axis_angle(z_axis(), 110.0);
+    let x = Vec3::new(1.0, 0.0, 0.0);
+
+    assert_vec3_eq(a.slerp(b, 0.0) * x, a * x);
+    assert_vec3_eq(a.slerp(b, 1.0) * x, b * x);
+    assert_vec3_eq(a.slerp(b, 0.5) * x, Quat::from_axis_angle(z_axis(), 60.0) * x);
+}
+
+#[test]
+fn slerp_moves_at_constant_speed() {
+    let a = Quat::identity();
+    let b = Quat::from_axis_angle(Vec3::new(1.0, 1.0, 0.0), 150.0);
+    let angle = |q: Quat| 2.0 * q.w.abs().min(1.0).acos().to_degrees();
+
+    for i in 0..=10 {
+        let t = i as f32 / 10.0;
+        let q = a.slerp(b, t);
+        assert!((q.length() - 1.0).abs() <= EPSILON);
+        assert!((angle(q) - 150.0 * t).abs() <= 1e-2, "t {}: {}°", t, angle(q));
+    }
+}
+
+#[test]
+fn slerp_takes_the_short_way() {
+    let a = Quat::from_axis_angle(z_axis(), 170.0);
+    let b = Quat::from_axis_angle(z_axis(), -170.0);
+
+    // through 180°, not back through 0°
+    let x = Vec3::new(1.0, 0.0, 0.0);
+    assert_vec3_eq(a.slerp(b, 0.5) * x, Vec3::new(-1.0, 0.0, 0.0));
+}
+
+#[test]
+fn slerp_between_close_orientations() {
+    let a = Quat::from_axis_angle(z_axis(), 20.0);
+    let b = Quat::from_axis_angle(z_axis(), 20.5);
+    let x = Vec3::new(1.0, 0.0, 0.0);
+
+    assert_vec3_eq(a.slerp(b, 0.5) * x, Quat::from_axis_angle(z_axis(), 20.25) * x);
+}
@@ -0,0 +1,60 @@
+//! Source line annotations of shader compile logs, in the formats the common drivers use.
+
+use kmscube::shader::annotate_log;
+
+const SOURCE: &str = "
+        uniform mat4 mvp;
+        attribute vec4 in_position;
+
+        void main()
+        {
+            gl_Position = mvp * in_positon;
+        }
+";
+
+#[test]
+fn mesa() {
+    let log = "0:7(33): error: `in_positon' undeclared\n";
+
+    assert_eq!(annotate_log(log, SOURCE), "\
+0:7(33): error: `in_positon' undeclared
+    7 | gl_Position = mvp * in_positon;
+");
+}
+
+#[test]
+fn error_prefixed() {
+    // Mali, Adreno and ANGLE
+    let log = "ERROR: 0:7: 'in_positon' : undeclared identifier\nERROR: 1 compilation errors.  No code generated.\n";
+
+    assert_eq!(annotate_log(log, SOURCE), "\
+ERROR: 0:7: 'in_positon' : undeclared identifier
+    7 | gl_Position = mvp * in_positon;
+ERROR: 1 compilation errors.  No code generated.
+");
+}
+
+#[test]
+fn nvidia() {
+    let log = "0(2) : warning C7022: unrecognized profile specifier \"mvp\"";
+
+    assert_eq!(annotate_log(log, SOURCE), "\
+0(2) : warning C7022: unrecognized profile specifier \"mvp\"
+    2 | uniform mat4 mvp;
+");
+}
+
+#[test]
+fn lines_outside_the_source_are_left_alone() {
+    let log = "0:0(1): error: syntax error\n0:42(1): error: unexpected end of file\n";
+
+    assert_eq!(annotate_log(log, SOURCE), log);
+}
+
+#[test]
+fn messages_without_a_location() {
+    let log = "error: vertex shader output `vColor' is not read by the fragment shader\n";
+
+    assert_eq!(annotate_log(log, SOURCE), log);
+    assert_eq!(annotate_log("", SOURCE), "");
+}
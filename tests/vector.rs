@@ -0,0 +1,67 @@
+//! Vector arithmetic and the `Mul` impls between matrices and vectors.
+
+mod common;
+
+use kmscube::es_matrix::ESMatrix;
+use kmscube::vector::{Vec3, Vec4};
+
+use common::assert_vec3_eq;
+
+#[test]
+fn arithmetic() {
+    let a = Vec3::new(1.0, 2.0, 3.0);
+    let b = Vec3::new(-4.0, 0.5, 2.0);
+
+    assert_eq!(a + b, Vec3::new(-3.0, 2.5, 5.0));
+    assert_eq!(a - b, Vec3::new(5.0, 1.5, 1.0));
+    assert_eq!(-a, Vec3::new(-1.0, -2.0, -3.0));
+    assert_eq!(a * 2.0, Vec3::new(2.0, 4.0, 6.0));
+    assert_eq!(a.dot(b), 3.0);
+    assert_eq!(Vec4::new(1.0, 2.0, 3.0, 4.0).dot(Vec4::new(1.0, 1.0, 1.0, 1.0)), 10.0);
+}
+
+#[test]
+fn cross_follows_the_right_hand_rule() {
+    let x = Vec3::new(1.0, 0.0, 0.0);
+    let y = Vec3::new(0.0, 1.0, 0.0);
+
+    assert_eq!(x.cross(y), Vec3::new(0.0, 0.0, 1.0));
+    assert_eq!(y.cross(x), Vec3::new(0.0, 0.0, -1.0));
+}
+
+#[test]
+fn normalize() {
+    assert_vec3_eq(Vec3::new(3.0, 0.0, 4.0).normalize().unwrap(), Vec3::new(0.6, 0.0, 0.8));
+    assert!(Vec3::default().normalize().is_none());
+}
+
+#[test]
+fn matrix_times_vector_transforms_like_the_shaders() {
+    let m = ESMatrix::identity().translate(1.0, 2.0, 3.0).scale(2.0, 2.0, 2.0);
+
+    assert_eq!(m * Vec4::new(1.0, 1.0, 1.0, 1.0), Vec4::new(3.0, 4.0, 5.0, 1.0));
+    // directions ignore the translation
+    assert_eq!(m * Vec4::new(1.0, 1.0, 1.0, 0.0), Vec4::new(2.0, 2.0, 2.0, 0.0));
+    assert_vec3_eq(m * Vec3::new(1.0, 1.0, 1.0), Vec3::new(3.0, 4.0, 5.0));
+}
+
+#[test]
+fn matrix_times_point_divides_by_w() {
+    let m = ESMatrix::identity().perspective(90.0, 1.0, 1.0, 3.0);
+
+    assert_vec3_eq(m * Vec3::new(1.0, 1.0, -1.0), Vec3::new(1.0, 1.0, -1.0));
+    assert_vec3_eq(m * Vec3::new(3.0, -3.0, -3.0), Vec3::new(1.0, -1.0, 1.0));
+}
+
+#[test]
+fn matrix_product_applies_the_right_operand_first() {
+    let translate = ESMatrix::identity().translate(0.0, 0.0, -5.0);
+    let scale = ESMatrix::identity().scale(2.0, 2.0, 2.0);
+    let p = Vec3::new(1.0, 0.0, 0.0);
+
+    assert_vec3_eq((translate * scale) * p, translate * (scale * p));
+    assert_vec3_eq((translate * scale) * p, Vec3::new(2.0, 0.0, -5.0));
+    assert_vec3_eq((scale * translate) * p, Vec3::new(2.0, 0.0, -10.0));
+    // the same order the builder methods compose in
+    assert_eq!((translate * scale).m(), ESMatrix::identity().translate(0.0, 0.0, -5.0).scale(2.0, 2.0, 2.0).m());
+}